@@ -0,0 +1,233 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    oscillator::Generator,
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+pub const MAX_PARTIALS: usize = 64;
+/// Samples between updates of the partial frequencies and amplitudes.
+const CONTROL_INTERVAL: usize = 64;
+
+#[derive(Clone)]
+pub struct AdditiveParams {
+    pub partials: u32,
+    /// Slope applied on top of the spectrum, in dB per octave.
+    pub tilt: f32,
+    /// Balance between odd and even partials, from only odd at -1 to only even at 1. The
+    /// fundamental counts as odd.
+    pub odd_even: f32,
+    /// Stretches the partials upwards like those of a stiff string.
+    pub inharmonicity: f32,
+    /// Amplitude of each partial, as drawn in the editor.
+    pub spectrum: [f32; MAX_PARTIALS],
+}
+
+impl Default for AdditiveParams {
+    fn default() -> Self {
+        Self {
+            partials: 32,
+            tilt: 0.0,
+            odd_even: 0.0,
+            inharmonicity: 0.0,
+            spectrum: std::array::from_fn(|i| 1.0 / (i + 1) as f32),
+        }
+    }
+}
+
+impl AdditiveParams {
+    /// Amplitude of the partial at `index`, with tilt and odd/even balance applied.
+    pub fn amplitude(&self, index: usize) -> f32 {
+        if index >= self.partials as usize {
+            return 0.0;
+        }
+        let number = (index + 1) as f32;
+        let balance = match index % 2 {
+            0 => (1.0 - self.odd_even).min(1.0),
+            _ => (1.0 + self.odd_even).min(1.0),
+        };
+        self.spectrum[index] * balance * 10f32.powf(self.tilt * number.log2() / 20.0)
+    }
+
+    /// Frequency of the partial at `index` relative to the fundamental.
+    pub fn ratio(&self, index: usize) -> f32 {
+        let number = (index + 1) as f32;
+        let stiffness = self.inharmonicity * 0.001;
+        number * (1.0 + stiffness * number * number).sqrt()
+    }
+
+    /// Serializes the spectrum for the plugin state.
+    pub fn spectrum_bytes(&self) -> Vec<u8> {
+        self.spectrum.iter().flat_map(|a| a.to_le_bytes()).collect()
+    }
+
+    pub fn load_spectrum(&mut self, data: &[u8]) {
+        for (amplitude, bytes) in self.spectrum.iter_mut().zip(data.chunks_exact(4)) {
+            *amplitude = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+}
+
+impl ParamGroup for AdditiveParams {
+    const COUNT: u32 = 4;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new(
+                "Partials",
+                "Additive",
+                1.0,
+                MAX_PARTIALS as f32,
+                Unit::Integer,
+            )),
+            1 => Some(ParamSpec::new(
+                "Tilt",
+                "Additive",
+                -12.0,
+                12.0,
+                Unit::Decibels,
+            )),
+            2 => Some(ParamSpec::new(
+                "Odd/Even",
+                "Additive",
+                -1.0,
+                1.0,
+                Unit::Percent,
+            )),
+            3 => Some(ParamSpec::new(
+                "Inharmonicity",
+                "Additive",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.partials as f32),
+            1 => Some(self.tilt),
+            2 => Some(self.odd_even),
+            3 => Some(self.inharmonicity),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.partials = value.round().clamp(1.0, MAX_PARTIALS as f32) as u32,
+            1 => self.tilt = value,
+            2 => self.odd_even = value,
+            3 => self.inharmonicity = value,
+            _ => {}
+        }
+    }
+}
+
+/// A bank of sine oscillators, each computed by rotating a complex phasor so that no sine
+/// has to be evaluated per sample.
+pub struct AdditiveVoice {
+    /// Current phasor of every partial, as real and imaginary parts.
+    phasors: [(f32, f32); MAX_PARTIALS],
+    /// Rotation applied to each phasor per sample.
+    rotations: [(f32, f32); MAX_PARTIALS],
+    /// Amplitude of every partial, ramped towards its target over each control interval so
+    /// that moving the spectrum doesn't step.
+    amplitudes: [f32; MAX_PARTIALS],
+    targets: [f32; MAX_PARTIALS],
+    countdown: usize,
+    /// Set by a new note, whose partials start at their targets instead of ramping.
+    starting: bool,
+}
+
+impl Default for AdditiveVoice {
+    fn default() -> Self {
+        Self {
+            phasors: [(1.0, 0.0); MAX_PARTIALS],
+            rotations: [(1.0, 0.0); MAX_PARTIALS],
+            amplitudes: [0.0; MAX_PARTIALS],
+            targets: [0.0; MAX_PARTIALS],
+            countdown: 0,
+            starting: true,
+        }
+    }
+}
+
+impl AdditiveVoice {
+    fn update(&mut self, params: &AdditiveParams, frequency: f32, sample_rate: f32) {
+        for (index, ((phasor, rotation), target)) in self
+            .phasors
+            .iter_mut()
+            .zip(&mut self.rotations)
+            .zip(&mut self.targets)
+            .enumerate()
+        {
+            let increment = frequency * params.ratio(index) / sample_rate;
+            *target = if increment < 0.5 {
+                params.amplitude(index)
+            } else {
+                0.0
+            };
+            let (sin, cos) = (increment * TAU).sin_cos();
+            *rotation = (cos, sin);
+
+            // Rounding errors slowly change the length of the phasors, so they are pulled
+            // back to the unit circle now and then
+            let scale = (3.0 - (phasor.0 * phasor.0 + phasor.1 * phasor.1)) * 0.5;
+            *phasor = (phasor.0 * scale, phasor.1 * scale);
+        }
+
+        // Normalize to the RMS level of the spectrum, so that the level doesn't grow with
+        // the number of partials
+        let power = self.targets.iter().map(|a| a * a).sum::<f32>() * 0.5;
+        if power > 0.0 {
+            let gain = 0.5 / power.sqrt();
+            self.targets.iter_mut().for_each(|a| *a *= gain);
+        }
+
+        if self.starting {
+            self.amplitudes = self.targets;
+            self.starting = false;
+        }
+    }
+}
+
+impl Generator for AdditiveVoice {
+    fn start(&mut self, _params: &Parameters, _key: u16, _velocity: f32) {
+        self.phasors = [(1.0, 0.0); MAX_PARTIALS];
+        self.countdown = 0;
+        self.starting = true;
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        if self.countdown == 0 {
+            self.update(&params.additive, frequency, sample_rate);
+            self.countdown = CONTROL_INTERVAL;
+        }
+        // Share of the remaining distance to the targets covered by this sample, which reaches
+        // them at the end of the interval
+        let ramp = 1.0 / self.countdown as f32;
+        self.countdown -= 1;
+
+        let partials = params.additive.partials as usize;
+        let mut sample = 0.0;
+        for (((phasor, rotation), amplitude), target) in self
+            .phasors
+            .iter_mut()
+            .zip(&self.rotations)
+            .zip(&mut self.amplitudes)
+            .zip(&self.targets)
+            .take(partials)
+        {
+            sample += phasor.1 * *amplitude;
+            *amplitude += (target - *amplitude) * ramp;
+            *phasor = (
+                phasor.0 * rotation.0 - phasor.1 * rotation.1,
+                phasor.0 * rotation.1 + phasor.1 * rotation.0,
+            );
+        }
+        sample
+    }
+}
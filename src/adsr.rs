@@ -35,7 +35,7 @@ impl ADSR {
                 } else {
                     ADSRState::Attack(sample + 1.0)
                 };
-                sample / attack_samples
+                progress(sample, attack_samples)
             }
             ADSRState::Decay(sample) => {
                 let decay_samples = self.envelope.decay * sample_rate;
@@ -44,7 +44,7 @@ impl ADSR {
                 } else {
                     ADSRState::Decay(sample + 1.0)
                 };
-                1.0 - (1.0 - self.envelope.sustain) * (sample / decay_samples)
+                1.0 - (1.0 - self.envelope.sustain) * progress(sample, decay_samples)
             }
             ADSRState::Sustain => self.envelope.sustain,
             ADSRState::Release(sample) => {
@@ -55,9 +55,28 @@ impl ADSR {
                 } else {
                     ADSRState::Release(sample + 1.0)
                 };
-                self.envelope.sustain * (1.0 - sample / release_samples)
+                self.envelope.sustain * (1.0 - progress(sample, release_samples))
             }
             ADSRState::Ended => 0.0,
         }
     }
 }
+
+/// How far into a stage of `total` samples we are, treating zero-length stages as complete.
+fn progress(sample: f32, total: f32) -> f32 {
+    if total > 0.0 {
+        (sample / total).min(1.0)
+    } else {
+        1.0
+    }
+}
+
+impl Default for ADSR {
+    /// An envelope that has already ended, used for voices that are not playing.
+    fn default() -> Self {
+        ADSR {
+            envelope: Envelope::default(),
+            state: ADSRState::Ended,
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    mixer::poly_blep,
+    oscillator::Generator,
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+choice! {
+    pub enum Waveform {
+        #[default]
+        Sine => "Sine",
+        Triangle => "Triangle",
+        Saw => "Saw",
+        Square => "Square",
+    }
+}
+
+impl Waveform {
+    /// Samples the waveform at `phase`, with the steps of the saw and square smoothed according
+    /// to how far the phase moves per sample.
+    fn sample(self, phase: f32, increment: f32) -> f32 {
+        let increment = increment.abs().min(0.5);
+        match self {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * phase - 1.0 - poly_blep(phase, increment),
+            Waveform::Square => {
+                let naive = if phase < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(phase, increment) - poly_blep((phase + 0.5) % 1.0, increment)
+            }
+        }
+    }
+}
+
+choice! {
+    pub enum Sync {
+        #[default]
+        Off => "Off",
+        On => "On",
+    }
+}
+
+#[derive(Clone)]
+pub struct AnalogParams {
+    pub osc1_waveform: Waveform,
+    pub osc2_waveform: Waveform,
+    pub osc2_semitones: f32,
+    pub osc2_fine: f32,
+    /// Balance between the two oscillators, where 0 is only oscillator 1.
+    pub mix: f32,
+    /// Restarts oscillator 1 whenever oscillator 2 starts a new cycle.
+    pub sync: Sync,
+    pub ring_modulation: f32,
+    /// Depth of the linear frequency modulation of oscillator 1 by oscillator 2.
+    pub cross_modulation: f32,
+}
+
+impl Default for AnalogParams {
+    fn default() -> Self {
+        Self {
+            osc1_waveform: Waveform::Sine,
+            osc2_waveform: Waveform::Saw,
+            osc2_semitones: 0.0,
+            osc2_fine: 0.0,
+            mix: 0.0,
+            sync: Sync::Off,
+            ring_modulation: 0.0,
+            cross_modulation: 0.0,
+        }
+    }
+}
+
+impl ParamGroup for AnalogParams {
+    const COUNT: u32 = 8;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice(
+                "Osc 1 Waveform",
+                "Analog",
+                Waveform::NAMES,
+            )),
+            1 => Some(ParamSpec::choice(
+                "Osc 2 Waveform",
+                "Analog",
+                Waveform::NAMES,
+            )),
+            2 => Some(ParamSpec::new(
+                "Osc 2 Semitones",
+                "Analog",
+                -24.0,
+                24.0,
+                Unit::Semitones,
+            )),
+            3 => Some(ParamSpec::new(
+                "Osc 2 Fine",
+                "Analog",
+                -100.0,
+                100.0,
+                Unit::Cents,
+            )),
+            4 => Some(ParamSpec::new("Osc Mix", "Analog", 0.0, 1.0, Unit::Percent)),
+            5 => Some(ParamSpec::choice("Hard Sync", "Analog", Sync::NAMES)),
+            6 => Some(ParamSpec::new(
+                "Ring Mod",
+                "Analog",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            7 => Some(ParamSpec::new(
+                "Cross Mod",
+                "Analog",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.osc1_waveform.value()),
+            1 => Some(self.osc2_waveform.value()),
+            2 => Some(self.osc2_semitones),
+            3 => Some(self.osc2_fine),
+            4 => Some(self.mix),
+            5 => Some(self.sync.value()),
+            6 => Some(self.ring_modulation),
+            7 => Some(self.cross_modulation),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.osc1_waveform = Waveform::from_value(value),
+            1 => self.osc2_waveform = Waveform::from_value(value),
+            2 => self.osc2_semitones = value.round(),
+            3 => self.osc2_fine = value,
+            4 => self.mix = value,
+            5 => self.sync = Sync::from_value(value),
+            6 => self.ring_modulation = value,
+            7 => self.cross_modulation = value,
+            _ => {}
+        }
+    }
+}
+
+/// A pair of oscillators, where oscillator 2 can sync, ring modulate and frequency modulate
+/// oscillator 1.
+#[derive(Default)]
+pub struct AnalogVoice {
+    phases: [f32; 2],
+}
+
+impl Generator for AnalogVoice {
+    fn start(&mut self, _params: &Parameters, _key: u16, _velocity: f32) {
+        self.phases = [0.0; 2];
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let analog = &params.analog;
+
+        let detune = analog.osc2_semitones + analog.osc2_fine / 100.0;
+        let increment2 = frequency * 2.0f32.powf(detune / 12.0) / sample_rate;
+        let osc2 = analog.osc2_waveform.sample(self.phases[1], increment2);
+
+        // Through-zero FM, so the phase of oscillator 1 may run backwards
+        let increment1 = frequency * (1.0 + osc2 * analog.cross_modulation * 4.0) / sample_rate;
+        let osc1 = analog.osc1_waveform.sample(self.phases[0], increment1);
+
+        let sample = osc1 * (1.0 - analog.mix) + osc2 * analog.mix;
+        let sample = sample * (1.0 - analog.ring_modulation) + osc1 * osc2 * analog.ring_modulation;
+
+        self.phases[0] = (self.phases[0] + increment1).rem_euclid(1.0);
+        self.phases[1] += increment2;
+        if self.phases[1] >= 1.0 {
+            self.phases[1] -= 1.0;
+            if analog.sync == Sync::On {
+                // Place oscillator 1 where it would have been had it restarted exactly at the wrap
+                self.phases[0] = (self.phases[1] * increment1 / increment2).rem_euclid(1.0);
+            }
+        }
+        sample
+    }
+}
@@ -0,0 +1,324 @@
+use clack_plugin::events::{
+    event_types::{NoteOffEvent, NoteOnEvent},
+    Match,
+};
+
+use crate::{
+    delay::Division,
+    notes::{Clock, NoteEvent},
+    params::{choice, ParamGroup, ParamSpec, Toggle, Unit},
+    random::Random,
+};
+
+/// Most notes the arpeggiator keeps track of at once. Storage is reserved up front so that
+/// playing never allocates on the audio thread.
+const MAX_NOTES: usize = 128;
+
+choice! {
+    pub enum Mode {
+        #[default]
+        Up => "Up",
+        Down => "Down",
+        UpDown => "Up/Down",
+        Random => "Random",
+        AsPlayed => "As Played",
+    }
+}
+
+#[derive(Clone)]
+pub struct ArpeggiatorParams {
+    pub enabled: Toggle,
+    pub mode: Mode,
+    /// Number of octaves the pattern climbs through.
+    pub octaves: f32,
+    pub rate: Division,
+    /// Length of each note, relative to the step.
+    pub gate: f32,
+    /// How far every second step is pushed back, relative to the step.
+    pub swing: f32,
+    /// Whether the notes keep playing after the keys are released, until new ones are pressed.
+    pub latch: Toggle,
+}
+
+impl Default for ArpeggiatorParams {
+    fn default() -> Self {
+        Self {
+            enabled: Toggle::Off,
+            mode: Mode::Up,
+            octaves: 1.0,
+            rate: Division::Sixteenth,
+            gate: 0.5,
+            swing: 0.0,
+            latch: Toggle::Off,
+        }
+    }
+}
+
+impl ParamGroup for ArpeggiatorParams {
+    const COUNT: u32 = 7;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice(
+                "Arpeggiator",
+                "Arpeggiator",
+                Toggle::NAMES,
+            )),
+            1 => Some(ParamSpec::choice("Arp Mode", "Arpeggiator", Mode::NAMES)),
+            2 => Some(ParamSpec::new(
+                "Arp Octaves",
+                "Arpeggiator",
+                1.0,
+                4.0,
+                Unit::Integer,
+            )),
+            3 => Some(ParamSpec::choice(
+                "Arp Rate",
+                "Arpeggiator",
+                Division::NAMES,
+            )),
+            4 => Some(ParamSpec::new(
+                "Arp Gate",
+                "Arpeggiator",
+                0.05,
+                1.0,
+                Unit::Percent,
+            )),
+            5 => Some(ParamSpec::new(
+                "Arp Swing",
+                "Arpeggiator",
+                0.0,
+                0.75,
+                Unit::Percent,
+            )),
+            6 => Some(ParamSpec::choice("Arp Latch", "Arpeggiator", Toggle::NAMES)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.enabled.value()),
+            1 => Some(self.mode.value()),
+            2 => Some(self.octaves),
+            3 => Some(self.rate.value()),
+            4 => Some(self.gate),
+            5 => Some(self.swing),
+            6 => Some(self.latch.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.enabled = Toggle::from_value(value),
+            1 => self.mode = Mode::from_value(value),
+            2 => self.octaves = value,
+            3 => self.rate = Division::from_value(value),
+            4 => self.gate = value,
+            5 => self.swing = value,
+            6 => self.latch = Toggle::from_value(value),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Note {
+    channel: u16,
+    key: u16,
+    velocity: f64,
+    /// Whether the key is still held down, as opposed to latched.
+    held: bool,
+}
+
+/// Turns the held keys into a rhythmic pattern of single notes. Steps are placed on the beat
+/// grid of the host while its transport runs, and on a free running clock otherwise.
+pub struct Arpeggiator {
+    clock: Clock,
+    /// Keys the pattern is made of, in the order they were pressed.
+    notes: Vec<Note>,
+    /// Scratch space for sorting the notes by pitch.
+    sorted: Vec<Note>,
+    /// Position of the next step on the grid before swing is applied, in beats. Empty while
+    /// no notes are held.
+    next_step: Option<f64>,
+    /// Number of steps played since the pattern started.
+    step: usize,
+    /// The note that is sounding, along with the position at which it ends.
+    playing: Option<(Note, f64)>,
+    random: Random,
+}
+
+impl Arpeggiator {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            clock: Clock::new(sample_rate),
+            notes: Vec::with_capacity(MAX_NOTES),
+            sorted: Vec::with_capacity(MAX_NOTES),
+            next_step: None,
+            step: 0,
+            playing: None,
+            random: Random::unique(),
+        }
+    }
+
+    /// Whether the arpeggiator has notes left to play.
+    pub fn is_active(&self) -> bool {
+        self.next_step.is_some() || self.playing.is_some()
+    }
+
+    pub fn note_on(&mut self, params: &ArpeggiatorParams, event: &NoteOnEvent) {
+        let (Match::Specific(channel), Match::Specific(key)) = (event.channel(), event.key())
+        else {
+            return;
+        };
+        // Playing after letting go of every key starts a new latched chord
+        if params.latch == Toggle::On && !self.notes.iter().any(|note| note.held) {
+            self.notes.clear();
+        }
+        self.notes
+            .retain(|note| (note.channel, note.key) != (channel, key));
+        if self.notes.len() < MAX_NOTES {
+            self.notes.push(Note {
+                channel,
+                key,
+                velocity: event.velocity(),
+                held: true,
+            });
+        }
+    }
+
+    /// Releases a key, returning whether it was one the arpeggiator was playing from.
+    pub fn note_off(&mut self, params: &ArpeggiatorParams, event: &NoteOffEvent) -> bool {
+        let Some(note) = self.notes.iter_mut().find(|note| {
+            note.held
+                && event.channel().as_specific() == Some(&note.channel)
+                && event.key().as_specific() == Some(&note.key)
+        }) else {
+            return false;
+        };
+        note.held = false;
+        if params.latch == Toggle::Off {
+            self.notes.retain(|note| note.held);
+        }
+        true
+    }
+
+    /// Follows the position of the host at the start of a block, if its transport is running.
+    pub fn sync(&mut self, params: &ArpeggiatorParams, position: Option<f64>) {
+        self.clock.sync(position);
+        if position.is_none() {
+            return;
+        }
+        // Jumps in the timeline, such as when the host loops, restart the grid
+        let length = params.rate.beats() as f64;
+        if let Some(grid) = self.next_step {
+            if (grid - self.clock.beat).abs() > length {
+                self.next_step = Some(self.clock.next_line(length));
+            }
+        }
+        if let Some((_, end)) = self.playing.as_mut() {
+            if *end > self.clock.beat + length {
+                *end = self.clock.beat;
+            }
+        }
+    }
+
+    /// Samples left until the arpeggiator plays or ends a note, or infinity if it is idle.
+    pub fn pending(&mut self, params: &ArpeggiatorParams, tempo: f64) -> f64 {
+        if params.enabled == Toggle::Off {
+            self.notes.clear();
+        } else if params.latch == Toggle::Off {
+            self.notes.retain(|note| note.held);
+        }
+
+        if self.notes.is_empty() {
+            self.next_step = None;
+        } else if self.next_step.is_none() {
+            self.next_step = Some(self.clock.start(params.rate.beats() as f64));
+            self.step = 0;
+        }
+
+        self.upcoming(params).map_or(f64::INFINITY, |(time, _)| {
+            self.clock.samples_until(tempo, time)
+        })
+    }
+
+    pub fn advance(&mut self, tempo: f64, frames: usize) {
+        self.clock.advance(tempo, frames);
+    }
+
+    /// Plays or ends a note if one is due at the current position.
+    pub fn trigger(&mut self, params: &ArpeggiatorParams, tempo: f64) -> Option<NoteEvent> {
+        if self.pending(params, tempo) > 0.0 {
+            return None;
+        }
+        let (time, is_end) = self.upcoming(params)?;
+
+        if is_end {
+            let (note, _) = self.playing.take()?;
+            return Some(NoteEvent::off(note.channel, note.key));
+        }
+
+        let note = self.pick(params);
+        let length = params.rate.beats() as f64;
+        let grid = self.next_step?;
+        let end = (time + length * params.gate as f64).min(grid + length);
+        self.playing = Some((note, end));
+        self.next_step = Some(grid + length);
+        self.step += 1;
+        Some(NoteEvent::on(note.channel, note.key, note.velocity))
+    }
+
+    /// Position of the next note to start or end, and whether it is an end.
+    fn upcoming(&self, params: &ArpeggiatorParams) -> Option<(f64, bool)> {
+        // Every second step of the grid is swung
+        let length = params.rate.beats() as f64;
+        let start = self
+            .next_step
+            .map(|grid| match (grid / length).round() as i64 % 2 {
+                0 => grid,
+                _ => grid + length * params.swing as f64,
+            });
+        let end = match params.enabled {
+            Toggle::Off => self.playing.map(|_| self.clock.beat),
+            Toggle::On => self.playing.map(|(_, end)| end),
+        };
+        match (start, end) {
+            (None, None) => None,
+            (Some(start), Some(end)) if start < end => Some((start, false)),
+            (_, Some(end)) => Some((end, true)),
+            (Some(start), None) => Some((start, false)),
+        }
+    }
+
+    /// Chooses the note of the current step.
+    fn pick(&mut self, params: &ArpeggiatorParams) -> Note {
+        self.sorted.clear();
+        self.sorted.extend_from_slice(&self.notes);
+        if params.mode != Mode::AsPlayed {
+            self.sorted.sort_unstable_by_key(|note| note.key);
+        }
+
+        let count = self.sorted.len();
+        let length = count * params.octaves.round().max(1.0) as usize;
+        let index = match params.mode {
+            Mode::Up | Mode::AsPlayed => self.step % length,
+            Mode::Down => length - 1 - self.step % length,
+            // Bounce between the ends without playing them twice
+            Mode::UpDown if length > 1 => {
+                let index = self.step % (2 * length - 2);
+                index.min(2 * length - 2 - index)
+            }
+            Mode::UpDown => 0,
+            Mode::Random => self.random.next_u32() as usize % length,
+        };
+
+        let note = self.sorted[index % count];
+        Note {
+            key: (note.key + 12 * (index / count) as u16).min(127),
+            ..note
+        }
+    }
+}
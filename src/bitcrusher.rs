@@ -0,0 +1,179 @@
+use crate::{
+    effects::Effect,
+    eq::{Biquad, Coefficients, BUTTERWORTH},
+    params::{ParamGroup, ParamSpec, Parameters, Toggle, Unit},
+    random::Random,
+};
+
+#[derive(Clone)]
+pub struct BitcrusherParams {
+    /// Resolution of the output, in bits.
+    pub bits: f32,
+    /// Rate at which the input is sampled and held, in Hz.
+    pub rate: f32,
+    /// Whether to filter out everything above half the reduced rate before sampling it.
+    pub anti_alias: Toggle,
+    /// Whether to add triangular noise of one step before quantising, which turns the
+    /// distortion of quiet signals into a steady hiss.
+    pub dither: Toggle,
+    /// Whether to feed the quantisation error back, pushing the noise towards high frequencies.
+    pub noise_shaping: Toggle,
+}
+
+impl Default for BitcrusherParams {
+    fn default() -> Self {
+        Self {
+            bits: 8.0,
+            rate: 11025.0,
+            anti_alias: Toggle::Off,
+            dither: Toggle::Off,
+            noise_shaping: Toggle::Off,
+        }
+    }
+}
+
+impl ParamGroup for BitcrusherParams {
+    const COUNT: u32 = 5;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new(
+                "Bitcrusher Bits",
+                "Bitcrusher",
+                1.0,
+                16.0,
+                Unit::Integer,
+            )),
+            1 => Some(ParamSpec::new(
+                "Bitcrusher Rate",
+                "Bitcrusher",
+                100.0,
+                48000.0,
+                Unit::Hertz,
+            )),
+            2 => Some(ParamSpec::choice(
+                "Bitcrusher Anti-alias",
+                "Bitcrusher",
+                Toggle::NAMES,
+            )),
+            3 => Some(ParamSpec::choice(
+                "Bitcrusher Dither",
+                "Bitcrusher",
+                Toggle::NAMES,
+            )),
+            4 => Some(ParamSpec::choice(
+                "Bitcrusher Noise Shaping",
+                "Bitcrusher",
+                Toggle::NAMES,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.bits),
+            1 => Some(self.rate),
+            2 => Some(self.anti_alias.value()),
+            3 => Some(self.dither.value()),
+            4 => Some(self.noise_shaping.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.bits = value,
+            1 => self.rate = value,
+            2 => self.anti_alias = Toggle::from_value(value),
+            3 => self.dither = Toggle::from_value(value),
+            4 => self.noise_shaping = Toggle::from_value(value),
+            _ => {}
+        }
+    }
+}
+
+/// The state of one channel.
+#[derive(Default)]
+struct Channel {
+    filters: [Biquad; 2],
+    /// The most recent quantised sample, held until the next one is taken.
+    held: f32,
+    /// Quantisation error of the previous sample, for noise shaping.
+    error: f32,
+}
+
+/// Lo-fi effect that samples the input at a lower rate and quantises it to fewer bits.
+pub struct Bitcrusher {
+    sample_rate: f32,
+    channels: [Channel; 2],
+    /// Rate the anti-alias filter was last tuned for, and its coefficients.
+    cutoff_rate: f32,
+    coefficients: [Coefficients; 2],
+    /// Progress towards taking the next sample, from 0 to 1.
+    phase: f32,
+    random: Random,
+}
+
+impl Bitcrusher {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            channels: Default::default(),
+            cutoff_rate: 0.0,
+            coefficients: BUTTERWORTH
+                .map(|q| Coefficients::pass(false, sample_rate, q, sample_rate)),
+            phase: 1.0,
+            random: Random::unique(),
+        }
+    }
+}
+
+impl Effect for Bitcrusher {
+    fn process(&mut self, params: &Parameters, _tempo: f64, left: &mut [f32], right: &mut [f32]) {
+        let crusher = &params.bitcrusher;
+        let rate = crusher.rate.min(self.sample_rate);
+        if crusher.anti_alias == Toggle::On && rate != self.cutoff_rate {
+            // Keep the cutoff a little below the new Nyquist frequency
+            self.coefficients =
+                BUTTERWORTH.map(|q| Coefficients::pass(false, rate * 0.45, q, self.sample_rate));
+            self.cutoff_rate = rate;
+        }
+        let increment = rate / self.sample_rate;
+        let step = 2f32.powf(1.0 - crusher.bits.round());
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            self.phase += increment;
+            let sample = self.phase >= 1.0;
+            if sample {
+                self.phase -= 1.0;
+            }
+
+            for (channel, output) in self.channels.iter_mut().zip([&mut *left, &mut *right]) {
+                let mut x = *output;
+                if crusher.anti_alias == Toggle::On {
+                    x = channel
+                        .filters
+                        .iter_mut()
+                        .zip(&self.coefficients)
+                        .fold(x, |x, (filter, c)| filter.process(c, x));
+                }
+
+                if sample {
+                    if crusher.noise_shaping == Toggle::On {
+                        x -= channel.error;
+                    }
+                    let dither = match crusher.dither {
+                        Toggle::Off => 0.0,
+                        Toggle::On => (self.random.next_f32() - self.random.next_f32()) * step,
+                    };
+                    let quantised = ((x + dither) / step).round() * step;
+                    channel.error = quantised - x;
+                    channel.held = quantised.clamp(-1.0, 1.0);
+                }
+
+                *output = channel.held;
+            }
+        }
+    }
+}
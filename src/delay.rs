@@ -0,0 +1,280 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    effects::Effect,
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+/// Longest delay the buffers can hold, in seconds. Synced times beyond this are cut short.
+const MAX_TIME: f32 = 4.0;
+
+choice! {
+    pub enum Mode {
+        #[default]
+        Stereo => "Stereo",
+        PingPong => "Ping-Pong",
+    }
+}
+
+choice! {
+    pub enum TimeMode {
+        #[default]
+        Free => "Milliseconds",
+        Synced => "Tempo Sync",
+    }
+}
+
+choice! {
+    pub enum Division {
+        ThirtySecond => "1/32",
+        SixteenthTriplet => "1/16T",
+        Sixteenth => "1/16",
+        SixteenthDotted => "1/16D",
+        EighthTriplet => "1/8T",
+        #[default]
+        Eighth => "1/8",
+        EighthDotted => "1/8D",
+        QuarterTriplet => "1/4T",
+        Quarter => "1/4",
+        QuarterDotted => "1/4D",
+        Half => "1/2",
+        Whole => "1/1",
+    }
+}
+
+impl Division {
+    /// Length of the division in quarter notes.
+    pub fn beats(self) -> f32 {
+        match self {
+            Division::ThirtySecond => 0.125,
+            Division::SixteenthTriplet => 0.25 * 2.0 / 3.0,
+            Division::Sixteenth => 0.25,
+            Division::SixteenthDotted => 0.375,
+            Division::EighthTriplet => 0.5 * 2.0 / 3.0,
+            Division::Eighth => 0.5,
+            Division::EighthDotted => 0.75,
+            Division::QuarterTriplet => 2.0 / 3.0,
+            Division::Quarter => 1.0,
+            Division::QuarterDotted => 1.5,
+            Division::Half => 2.0,
+            Division::Whole => 4.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DelayParams {
+    pub mode: Mode,
+    pub time_mode: TimeMode,
+    /// Delay time when not synced, in milliseconds.
+    pub time: f32,
+    pub division: Division,
+    pub feedback: f32,
+    /// Cutoff of the highpass in the feedback path, in Hz.
+    pub low_cut: f32,
+    /// Cutoff of the lowpass in the feedback path, in Hz.
+    pub high_cut: f32,
+}
+
+impl Default for DelayParams {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Stereo,
+            time_mode: TimeMode::Free,
+            time: 375.0,
+            division: Division::Eighth,
+            feedback: 0.4,
+            low_cut: 100.0,
+            high_cut: 8000.0,
+        }
+    }
+}
+
+impl DelayParams {
+    /// Delay time in seconds at the given tempo.
+    pub fn seconds(&self, tempo: f64) -> f32 {
+        match self.time_mode {
+            TimeMode::Free => self.time / 1000.0,
+            TimeMode::Synced => self.division.beats() * 60.0 / tempo as f32,
+        }
+    }
+}
+
+impl ParamGroup for DelayParams {
+    const COUNT: u32 = 7;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Delay Mode", "Delay", Mode::NAMES)),
+            1 => Some(ParamSpec::choice(
+                "Delay Time Mode",
+                "Delay",
+                TimeMode::NAMES,
+            )),
+            2 => Some(ParamSpec::new(
+                "Delay Time",
+                "Delay",
+                1.0,
+                2000.0,
+                Unit::Milliseconds,
+            )),
+            3 => Some(ParamSpec::choice(
+                "Delay Division",
+                "Delay",
+                Division::NAMES,
+            )),
+            4 => Some(ParamSpec::new(
+                "Delay Feedback",
+                "Delay",
+                0.0,
+                0.95,
+                Unit::Percent,
+            )),
+            5 => Some(ParamSpec::new(
+                "Delay Low Cut",
+                "Delay",
+                20.0,
+                2000.0,
+                Unit::Hertz,
+            )),
+            6 => Some(ParamSpec::new(
+                "Delay High Cut",
+                "Delay",
+                1000.0,
+                20000.0,
+                Unit::Hertz,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.mode.value()),
+            1 => Some(self.time_mode.value()),
+            2 => Some(self.time),
+            3 => Some(self.division.value()),
+            4 => Some(self.feedback),
+            5 => Some(self.low_cut),
+            6 => Some(self.high_cut),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.mode = Mode::from_value(value),
+            1 => self.time_mode = TimeMode::from_value(value),
+            2 => self.time = value,
+            3 => self.division = Division::from_value(value),
+            4 => self.feedback = value,
+            5 => self.low_cut = value,
+            6 => self.high_cut = value,
+            _ => {}
+        }
+    }
+}
+
+/// One channel of the delay, with the filters of its feedback path.
+struct Line {
+    buffer: Vec<f32>,
+    lowpass: f32,
+    highpass: f32,
+}
+
+impl Line {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length],
+            lowpass: 0.0,
+            highpass: 0.0,
+        }
+    }
+
+    /// Reads `delay` samples behind `position`, interpolating between samples.
+    fn read(&self, position: usize, delay: f32) -> f32 {
+        let length = self.buffer.len();
+        let offset = delay as usize;
+        let t = delay - offset as f32;
+        let a = self.buffer[(position + length - offset) % length];
+        let b = self.buffer[(position + 2 * length - offset - 1) % length];
+        a + (b - a) * t
+    }
+
+    /// Filters a sample on its way back into the delay.
+    fn filter(&mut self, x: f32, lowpass: f32, highpass: f32) -> f32 {
+        self.lowpass += (x - self.lowpass) * lowpass;
+        self.highpass += (self.lowpass - self.highpass) * highpass;
+        self.lowpass - self.highpass
+    }
+}
+
+/// Stereo delay with filtered feedback, which can also bounce the echoes between the
+/// channels.
+pub struct Delay {
+    sample_rate: f32,
+    lines: [Line; 2],
+    position: usize,
+    /// Current delay in samples, gliding towards the set time to avoid clicks.
+    delay: f32,
+}
+
+impl Delay {
+    pub fn new(sample_rate: f32) -> Self {
+        let length = (MAX_TIME * sample_rate) as usize + 2;
+        Self {
+            sample_rate,
+            lines: [Line::new(length), Line::new(length)],
+            position: 0,
+            delay: 0.0,
+        }
+    }
+}
+
+impl Effect for Delay {
+    fn process(&mut self, params: &Parameters, tempo: f64, left: &mut [f32], right: &mut [f32]) {
+        let delay = &params.delay;
+        let coefficient = |cutoff: f32| 1.0 - (-TAU * cutoff / self.sample_rate).exp();
+        let (lowpass, highpass) = (coefficient(delay.high_cut), coefficient(delay.low_cut));
+        let target = (delay.seconds(tempo).min(MAX_TIME) * self.sample_rate).max(1.0);
+        if self.delay == 0.0 {
+            self.delay = target;
+        }
+
+        let length = self.lines[0].buffer.len();
+        let [left_line, right_line] = &mut self.lines;
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            self.delay += (target - self.delay) * 0.001;
+            let echoes = (
+                left_line.read(self.position, self.delay),
+                right_line.read(self.position, self.delay),
+            );
+            let feedback = (
+                left_line.filter(echoes.0, lowpass, highpass) * delay.feedback,
+                right_line.filter(echoes.1, lowpass, highpass) * delay.feedback,
+            );
+
+            let inputs = match delay.mode {
+                Mode::Stereo => (*left + feedback.0, *right + feedback.1),
+                // The input only enters the left line, and each line feeds the other
+                Mode::PingPong => ((*left + *right) * 0.5 + feedback.1, feedback.0),
+            };
+            left_line.buffer[self.position] = inputs.0;
+            right_line.buffer[self.position] = inputs.1;
+            self.position = (self.position + 1) % length;
+
+            *left = echoes.0;
+            *right = echoes.1;
+        }
+    }
+
+    fn tail(&self, params: &Parameters, tempo: f64) -> f32 {
+        // Time until the echoes have fallen by 60 dB
+        let delay = &params.delay;
+        let time = delay.seconds(tempo).min(MAX_TIME);
+        match delay.feedback {
+            0.0 => time,
+            feedback => time * (1.0 + (0.001f32).ln() / feedback.ln()),
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    fm::{FrequencyMode, ALGORITHMS_6OP},
+    oscillator::Generator,
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+const CURVES: &[&str] = &["-Lin", "-Exp", "+Exp", "+Lin"];
+
+/// Operator that receives feedback in each algorithm, and the operator whose output is fed back.
+const FEEDBACK: [(usize, usize); 32] = [
+    (5, 5),
+    (1, 1),
+    (5, 5),
+    (5, 3),
+    (5, 5),
+    (5, 4),
+    (5, 5),
+    (3, 3),
+    (1, 1),
+    (2, 2),
+    (5, 5),
+    (1, 1),
+    (5, 5),
+    (5, 5),
+    (1, 1),
+    (5, 5),
+    (1, 1),
+    (2, 2),
+    (5, 5),
+    (2, 2),
+    (2, 2),
+    (5, 5),
+    (5, 5),
+    (5, 5),
+    (5, 5),
+    (5, 5),
+    (2, 2),
+    (4, 4),
+    (5, 5),
+    (4, 4),
+    (5, 5),
+    (5, 5),
+];
+
+/// Output levels below 20 do not follow the 0.75 dB per step of the rest of the range.
+const LEVEL_LUT: [u8; 20] = [
+    0, 5, 9, 13, 17, 20, 23, 25, 27, 29, 31, 33, 35, 37, 39, 41, 42, 43, 45, 46,
+];
+
+const EXP_SCALE: [u8; 33] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 14, 16, 19, 23, 27, 33, 39, 47, 56, 66, 80, 94, 110, 126,
+    142, 158, 174, 190, 206, 222, 238, 250,
+];
+
+/// Name, maximum value and unit of every operator parameter, in the order they are exposed.
+const OPERATOR_PARAMS: [(&str, u8, Unit); 20] = [
+    ("Rate 1", 99, Unit::Integer),
+    ("Rate 2", 99, Unit::Integer),
+    ("Rate 3", 99, Unit::Integer),
+    ("Rate 4", 99, Unit::Integer),
+    ("Level 1", 99, Unit::Integer),
+    ("Level 2", 99, Unit::Integer),
+    ("Level 3", 99, Unit::Integer),
+    ("Level 4", 99, Unit::Integer),
+    ("Break Point", 99, Unit::Integer),
+    ("Left Depth", 99, Unit::Integer),
+    ("Right Depth", 99, Unit::Integer),
+    ("Left Curve", 3, Unit::Choice(CURVES)),
+    ("Right Curve", 3, Unit::Choice(CURVES)),
+    ("Rate Scaling", 7, Unit::Integer),
+    ("Detune", 14, Unit::Integer),
+    ("Velocity Sensitivity", 7, Unit::Integer),
+    ("Output Level", 99, Unit::Integer),
+    ("Mode", 1, Unit::Choice(FrequencyMode::NAMES)),
+    ("Coarse", 31, Unit::Integer),
+    ("Fine", 99, Unit::Integer),
+];
+
+/// A single DX7 operator, with values in the same ranges as the synthesizer's own parameters.
+#[derive(Clone)]
+pub struct Dx7Operator {
+    pub rates: [u8; 4],
+    pub levels: [u8; 4],
+    pub break_point: u8,
+    pub left_depth: u8,
+    pub right_depth: u8,
+    pub left_curve: u8,
+    pub right_curve: u8,
+    pub rate_scaling: u8,
+    pub detune: u8,
+    pub velocity_sensitivity: u8,
+    pub output_level: u8,
+    pub mode: FrequencyMode,
+    pub coarse: u8,
+    pub fine: u8,
+}
+
+impl Default for Dx7Operator {
+    fn default() -> Self {
+        Self {
+            rates: [99, 99, 99, 99],
+            levels: [99, 99, 99, 0],
+            break_point: 39,
+            left_depth: 0,
+            right_depth: 0,
+            left_curve: 0,
+            right_curve: 0,
+            rate_scaling: 0,
+            detune: 7,
+            velocity_sensitivity: 0,
+            output_level: 0,
+            mode: FrequencyMode::Ratio,
+            coarse: 1,
+            fine: 0,
+        }
+    }
+}
+
+impl Dx7Operator {
+    fn get(&self, index: u32) -> Option<u8> {
+        match index {
+            0..=3 => Some(self.rates[index as usize]),
+            4..=7 => Some(self.levels[index as usize - 4]),
+            8 => Some(self.break_point),
+            9 => Some(self.left_depth),
+            10 => Some(self.right_depth),
+            11 => Some(self.left_curve),
+            12 => Some(self.right_curve),
+            13 => Some(self.rate_scaling),
+            14 => Some(self.detune),
+            15 => Some(self.velocity_sensitivity),
+            16 => Some(self.output_level),
+            17 => Some(self.mode as u8),
+            18 => Some(self.coarse),
+            19 => Some(self.fine),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: u8) {
+        let Some((_, max, _)) = OPERATOR_PARAMS.get(index as usize) else {
+            return;
+        };
+        let value = value.min(*max);
+        match index {
+            0..=3 => self.rates[index as usize] = value,
+            4..=7 => self.levels[index as usize - 4] = value,
+            8 => self.break_point = value,
+            9 => self.left_depth = value,
+            10 => self.right_depth = value,
+            11 => self.left_curve = value,
+            12 => self.right_curve = value,
+            13 => self.rate_scaling = value,
+            14 => self.detune = value,
+            15 => self.velocity_sensitivity = value,
+            16 => self.output_level = value,
+            17 => self.mode = FrequencyMode::from_value(value as f32),
+            18 => self.coarse = value,
+            _ => self.fine = value,
+        }
+    }
+
+    /// Frequency of the operator, either as a multiple of the note frequency or in Hz.
+    fn frequency(&self, key: f32) -> f32 {
+        match self.mode {
+            FrequencyMode::Ratio => {
+                let coarse = if self.coarse == 0 {
+                    0.5
+                } else {
+                    self.coarse as f32
+                };
+                // Detune is measured in steps that are relatively wider for lower notes
+                let log_frequency = (440.0 * 2f32.powf((key - 69.0) / 12.0)).log2();
+                let detune = 0.0209 * (-0.396 * log_frequency).exp() / 7.0
+                    * log_frequency
+                    * (self.detune as f32 - 7.0);
+                coarse * (1.0 + self.fine as f32 * 0.01) * 2f32.powf(detune)
+            }
+            FrequencyMode::Fixed => {
+                10f32.powf((self.coarse & 3) as f32 + self.fine as f32 * 0.01)
+                    * 2f32.powf(0.0008 * (self.detune as f32 - 7.0).max(0.0))
+            }
+        }
+    }
+
+    /// Attenuation caused by the keyboard level scaling, in output level steps.
+    fn level_scaling(&self, key: i32) -> i32 {
+        let offset = key - self.break_point as i32 - 17;
+        if offset >= 0 {
+            scale_curve((offset + 1) / 3, self.right_depth, self.right_curve)
+        } else {
+            scale_curve(-(offset - 1) / 3, self.left_depth, self.left_curve)
+        }
+    }
+}
+
+fn scale_curve(group: i32, depth: u8, curve: u8) -> i32 {
+    let scale = if curve == 0 || curve == 3 {
+        (group * depth as i32 * 329) >> 12
+    } else {
+        let exp = EXP_SCALE[(group.max(0) as usize).min(EXP_SCALE.len() - 1)] as i32;
+        (exp * depth as i32 * 329) >> 15
+    };
+    if curve < 2 {
+        -scale
+    } else {
+        scale
+    }
+}
+
+fn scale_output_level(level: u8) -> i32 {
+    match LEVEL_LUT.get(level as usize) {
+        Some(level) => *level as i32,
+        None => 28 + level.min(99) as i32,
+    }
+}
+
+/// A DX7 patch, with everything but the LFO and pitch envelope.
+#[derive(Clone)]
+pub struct Dx7Params {
+    pub operator: [Dx7Operator; 6],
+    /// One-based algorithm number.
+    pub algorithm: u8,
+    pub feedback: u8,
+    pub key_sync: bool,
+    /// Transposition in semitones, where 24 plays notes at their original pitch.
+    pub transpose: u8,
+}
+
+impl Default for Dx7Params {
+    fn default() -> Self {
+        let mut operator: [Dx7Operator; 6] = Default::default();
+        operator[0].output_level = 99;
+        Self {
+            operator,
+            algorithm: 1,
+            feedback: 0,
+            key_sync: true,
+            transpose: 24,
+        }
+    }
+}
+
+const GLOBAL_PARAMS: u32 = 4;
+
+impl ParamGroup for Dx7Params {
+    const COUNT: u32 = GLOBAL_PARAMS + 6 * OPERATOR_PARAMS.len() as u32;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new("Algorithm", "DX7", 1.0, 32.0, Unit::Integer)),
+            1 => Some(ParamSpec::new("Feedback", "DX7", 0.0, 7.0, Unit::Integer)),
+            2 => Some(ParamSpec::new("Transpose", "DX7", 0.0, 48.0, Unit::Integer)),
+            3 => Some(ParamSpec::choice("Key Sync", "DX7", &["Off", "On"])),
+            _ if index < Self::COUNT => {
+                let operator = (index - GLOBAL_PARAMS) / OPERATOR_PARAMS.len() as u32 + 1;
+                let (name, max, unit) = OPERATOR_PARAMS
+                    [((index - GLOBAL_PARAMS) % OPERATOR_PARAMS.len() as u32) as usize];
+                Some(ParamSpec::new(
+                    format!("Op {} {}", operator, name),
+                    format!("DX7/Operator {}", operator),
+                    0.0,
+                    max as f32,
+                    unit,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.algorithm as f32),
+            1 => Some(self.feedback as f32),
+            2 => Some(self.transpose as f32),
+            3 => Some(self.key_sync as u8 as f32),
+            _ => {
+                let index = index - GLOBAL_PARAMS;
+                self.operator
+                    .get((index / OPERATOR_PARAMS.len() as u32) as usize)?
+                    .get(index % OPERATOR_PARAMS.len() as u32)
+                    .map(f32::from)
+            }
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        let value = value.round().max(0.0) as u8;
+        match index {
+            0 => self.algorithm = value.clamp(1, 32),
+            1 => self.feedback = value.min(7),
+            2 => self.transpose = value.min(48),
+            3 => self.key_sync = value != 0,
+            _ => {
+                let index = index - GLOBAL_PARAMS;
+                if let Some(operator) = self
+                    .operator
+                    .get_mut((index / OPERATOR_PARAMS.len() as u32) as usize)
+                {
+                    operator.set(index % OPERATOR_PARAMS.len() as u32, value);
+                }
+            }
+        }
+    }
+}
+
+/// The four-rate, four-level envelope of a DX7 operator. Levels are tracked in steps of 1/256
+/// of an octave, with the same rate and curve behaviour as the original hardware.
+#[derive(Clone, Default)]
+struct Dx7Envelope {
+    rates: [u8; 4],
+    levels: [u8; 4],
+    output_level: f32,
+    rate_scaling: i32,
+    level: f32,
+    target: f32,
+    increment: f32,
+    rising: bool,
+    stage: usize,
+    down: bool,
+}
+
+impl Dx7Envelope {
+    fn start(&mut self, operator: &Dx7Operator, output_level: f32, rate_scaling: i32) {
+        self.rates = operator.rates;
+        self.levels = operator.levels;
+        self.output_level = output_level;
+        self.rate_scaling = rate_scaling;
+        self.level = 0.0;
+        self.down = true;
+        self.advance(0);
+    }
+
+    fn release(&mut self) {
+        if self.down {
+            self.down = false;
+            self.advance(3);
+        }
+    }
+
+    fn advance(&mut self, stage: usize) {
+        self.stage = stage;
+        if stage < 4 {
+            let level = (scale_output_level(self.levels[stage]) >> 1) as f32 * 64.0
+                + self.output_level
+                - 4256.0;
+            self.target = level.max(16.0);
+            self.rising = self.target > self.level;
+            let rate = (((self.rates[stage] as i32 * 41) >> 6) + self.rate_scaling).min(63);
+            self.increment = (4 + (rate & 3)) as f32 * 2f32.powi(2 + (rate >> 2)) / 65536.0;
+        }
+    }
+
+    fn process(&mut self, sample_rate: f32) -> f32 {
+        // The original rates were defined for a 44.1 kHz clock
+        let increment = self.increment * 44100.0 / sample_rate;
+        if self.stage < 3 || (self.stage < 4 && !self.down) {
+            if self.rising {
+                // Attacks jump past the inaudible range and slow down as they get louder
+                self.level = self.level.max(1716.0);
+                self.level += (17.0 - (self.level / 256.0).floor()) * increment;
+                if self.level >= self.target {
+                    self.level = self.target;
+                    self.advance(self.stage + 1);
+                }
+            } else {
+                self.level -= increment;
+                if self.level <= self.target {
+                    self.level = self.target;
+                    self.advance(self.stage + 1);
+                }
+            }
+        }
+        2f32.powf(self.level / 256.0 - 14.0)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stage >= 4
+    }
+}
+
+#[derive(Default)]
+pub struct Dx7Voice {
+    algorithm: usize,
+    feedback: f32,
+    transpose: f32,
+    ratios: [f32; 6],
+    fixed: [bool; 6],
+    phases: [f32; 6],
+    /// The last two outputs of the operator that is fed back
+    history: [f32; 2],
+    envelopes: [Dx7Envelope; 6],
+}
+
+impl Generator for Dx7Voice {
+    fn start(&mut self, params: &Parameters, key: u16, velocity: f32) {
+        let dx7 = &params.dx7;
+        let key = key as i32 + dx7.transpose as i32 - 24;
+        self.algorithm = (dx7.algorithm.clamp(1, 32) - 1) as usize;
+        self.feedback = if dx7.feedback == 0 {
+            0.0
+        } else {
+            2f32.powi(dx7.feedback as i32 - 8)
+        };
+        self.transpose = 2f32.powf((dx7.transpose as f32 - 24.0) / 12.0);
+        self.history = [0.0; 2];
+        if dx7.key_sync {
+            self.phases = [0.0; 6];
+        }
+
+        for (op, operator) in dx7.operator.iter().enumerate() {
+            self.ratios[op] = operator.frequency(key as f32);
+            self.fixed[op] = operator.mode == FrequencyMode::Fixed;
+
+            let level =
+                (scale_output_level(operator.output_level) + operator.level_scaling(key)).min(127);
+            let velocity_scaling = operator.velocity_sensitivity as f32 * (1.0 - velocity) * 6.0;
+            let output_level = ((level as f32 - velocity_scaling) * 32.0).max(0.0);
+            let rate_scaling = (operator.rate_scaling as i32 * (key / 3 - 7).clamp(0, 31)) >> 3;
+            self.envelopes[op].start(operator, output_level, rate_scaling);
+        }
+    }
+
+    fn release(&mut self) {
+        self.envelopes.iter_mut().for_each(Dx7Envelope::release);
+    }
+
+    fn next_sample(&mut self, _params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let algorithm = &ALGORITHMS_6OP[self.algorithm];
+        let (feedback_target, feedback_source) = FEEDBACK[self.algorithm];
+
+        let mut outputs = [0.0; 6];
+        let mut sample = 0.0;
+        for op in (0..6).rev() {
+            let mut modulation = (op + 1..6)
+                .filter(|modulator| algorithm.modulators[op] & (1 << modulator) != 0)
+                .map(|modulator| outputs[modulator])
+                .sum::<f32>();
+            if op == feedback_target {
+                modulation += (self.history[0] + self.history[1]) * 0.5 * self.feedback;
+            }
+
+            let output = ((self.phases[op] + modulation) * TAU).sin()
+                * self.envelopes[op].process(sample_rate);
+            outputs[op] = output;
+            if op == feedback_source {
+                self.history = [self.history[1], output];
+            }
+            if algorithm.carriers & (1 << op) != 0 {
+                sample += output;
+            }
+
+            let increment = if self.fixed[op] {
+                self.ratios[op]
+            } else {
+                frequency * self.transpose * self.ratios[op]
+            } / sample_rate;
+            self.phases[op] = (self.phases[op] + increment) % 1.0;
+        }
+
+        sample * 0.25
+    }
+
+    fn has_envelope(&self) -> bool {
+        true
+    }
+
+    fn is_finished(&self) -> bool {
+        let carriers = ALGORITHMS_6OP[self.algorithm].carriers;
+        (0..6)
+            .filter(|op| carriers & (1 << op) != 0)
+            .all(|op| self.envelopes[op].is_finished())
+    }
+}
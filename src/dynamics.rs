@@ -0,0 +1,240 @@
+use crate::params::{choice, ParamGroup, ParamSpec, Toggle, Unit};
+
+/// How far the limiter looks ahead, in milliseconds. The output is always delayed by this much
+/// so that the latency reported to the host never changes.
+const LOOKAHEAD: f32 = 2.0;
+
+choice! {
+    pub enum LimiterMode {
+        Off => "Off",
+        SoftClip => "Soft Clip",
+        #[default]
+        Limiter => "Limiter",
+    }
+}
+
+#[derive(Clone)]
+pub struct DynamicsParams {
+    pub compressor: Toggle,
+    /// Level above which the compressor starts reducing gain, in dB.
+    pub threshold: f32,
+    pub ratio: f32,
+    /// Attack time of the compressor, in milliseconds.
+    pub attack: f32,
+    /// Release time of the compressor and limiter, in milliseconds.
+    pub release: f32,
+    /// Gain after the compressor, in dB.
+    pub makeup: f32,
+    pub limiter: LimiterMode,
+    /// Highest level let through by the limiter or clipper, in dB.
+    pub ceiling: f32,
+}
+
+impl Default for DynamicsParams {
+    fn default() -> Self {
+        Self {
+            compressor: Toggle::Off,
+            threshold: -12.0,
+            ratio: 4.0,
+            attack: 10.0,
+            release: 100.0,
+            makeup: 0.0,
+            limiter: LimiterMode::Limiter,
+            ceiling: -0.3,
+        }
+    }
+}
+
+impl ParamGroup for DynamicsParams {
+    const COUNT: u32 = 8;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Compressor", "Output", Toggle::NAMES)),
+            1 => Some(ParamSpec::new(
+                "Threshold",
+                "Output",
+                -48.0,
+                0.0,
+                Unit::Decibels,
+            )),
+            2 => Some(ParamSpec::new("Ratio", "Output", 1.0, 20.0, Unit::Ratio)),
+            3 => Some(ParamSpec::new(
+                "Attack",
+                "Output",
+                0.1,
+                100.0,
+                Unit::Milliseconds,
+            )),
+            4 => Some(ParamSpec::new(
+                "Release",
+                "Output",
+                10.0,
+                1000.0,
+                Unit::Milliseconds,
+            )),
+            5 => Some(ParamSpec::new(
+                "Makeup",
+                "Output",
+                0.0,
+                24.0,
+                Unit::Decibels,
+            )),
+            6 => Some(ParamSpec::choice("Limiter", "Output", LimiterMode::NAMES)),
+            7 => Some(ParamSpec::new(
+                "Ceiling",
+                "Output",
+                -12.0,
+                0.0,
+                Unit::Decibels,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.compressor.value()),
+            1 => Some(self.threshold),
+            2 => Some(self.ratio),
+            3 => Some(self.attack),
+            4 => Some(self.release),
+            5 => Some(self.makeup),
+            6 => Some(self.limiter.value()),
+            7 => Some(self.ceiling),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.compressor = Toggle::from_value(value),
+            1 => self.threshold = value,
+            2 => self.ratio = value,
+            3 => self.attack = value,
+            4 => self.release = value,
+            5 => self.makeup = value,
+            6 => self.limiter = LimiterMode::from_value(value),
+            7 => self.ceiling = value,
+            _ => {}
+        }
+    }
+}
+
+/// Coefficient of a one-pole smoother that settles in about `ms` milliseconds.
+fn smoothing(ms: f32, sample_rate: f32) -> f32 {
+    1.0 - (-1.0 / (ms * 0.001 * sample_rate).max(1.0)).exp()
+}
+
+/// The final stage before the output: an optional compressor followed by a look-ahead limiter
+/// or soft clipper, keeping the sum of all voices and effects below full scale.
+pub struct Dynamics {
+    sample_rate: f32,
+    /// Gain reduction of the compressor, in dB.
+    reduction: f32,
+    /// Input delayed by the look-ahead, for each channel.
+    delay: [Vec<f32>; 2],
+    /// Lowest gain needed by any sample within the look-ahead window.
+    gains: Vec<f32>,
+    /// Smoothed gains, averaged over the look-ahead window so that the limiter has fully
+    /// closed by the time a peak comes out of the delay.
+    held: Vec<f32>,
+    held_sum: f32,
+    /// Gain of the limiter before averaging, recovering at the release speed.
+    gain: f32,
+    position: usize,
+}
+
+impl Dynamics {
+    pub fn new(sample_rate: f32) -> Self {
+        let length = Self::latency(sample_rate) as usize + 1;
+        Self {
+            sample_rate,
+            reduction: 0.0,
+            delay: [vec![0.0; length], vec![0.0; length]],
+            gains: vec![1.0; length],
+            held: vec![1.0; length],
+            held_sum: length as f32,
+            gain: 1.0,
+            position: 0,
+        }
+    }
+
+    /// Delay added by the look-ahead, in samples.
+    pub fn latency(sample_rate: f32) -> u32 {
+        (LOOKAHEAD * sample_rate / 1000.0) as u32
+    }
+
+    /// Length of the tail added by the look-ahead, in samples. Everything before the output
+    /// stage ends that much later.
+    pub fn tail(&self) -> u32 {
+        Self::latency(self.sample_rate)
+    }
+
+    /// Whether audio is still waiting in the look-ahead delay, so the plugin must not sleep.
+    pub fn is_active(&self) -> bool {
+        self.delay.iter().flatten().any(|sample| *sample != 0.0)
+    }
+
+    pub fn process(&mut self, params: &DynamicsParams, left: &mut [f32], right: &mut [f32]) {
+        let attack = smoothing(params.attack, self.sample_rate);
+        let release = smoothing(params.release, self.sample_rate);
+        let makeup = 10f32.powf(params.makeup / 20.0);
+        let ceiling = 10f32.powf(params.ceiling / 20.0);
+        let length = self.gains.len();
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            let (mut l, mut r) = (*left, *right);
+
+            if params.compressor == Toggle::On {
+                let level = 20.0 * l.abs().max(r.abs()).max(1e-6).log10();
+                let target = (level - params.threshold).max(0.0) * (1.0 - 1.0 / params.ratio);
+                let speed = if target > self.reduction {
+                    attack
+                } else {
+                    release
+                };
+                self.reduction += (target - self.reduction) * speed;
+                let gain = 10f32.powf(-self.reduction / 20.0) * makeup;
+                (l, r) = (l * gain, r * gain);
+            }
+
+            // Gain that brings this sample down to the ceiling
+            let peak = l.abs().max(r.abs());
+            self.gains[self.position] = if peak > ceiling { ceiling / peak } else { 1.0 };
+            let lowest = self.gains.iter().fold(1.0f32, |a, &b| a.min(b));
+            // Let go of the gain reduction gradually once the peaks have passed
+            self.gain = if lowest < self.gain {
+                lowest
+            } else {
+                self.gain + (lowest - self.gain) * release
+            };
+            self.held_sum += self.gain - self.held[self.position];
+            self.held[self.position] = self.gain;
+            let gain = self.held_sum / length as f32;
+            if self.position == 0 {
+                // Keeps rounding errors from building up in the running sum
+                self.held_sum = self.held.iter().sum();
+            }
+
+            let [delay_left, delay_right] = &mut self.delay;
+            let next = (self.position + 1) % length;
+            delay_left[self.position] = l;
+            delay_right[self.position] = r;
+            let (l, r) = (delay_left[next], delay_right[next]);
+            self.position = next;
+
+            (*left, *right) = match params.limiter {
+                LimiterMode::Off => (l, r),
+                LimiterMode::SoftClip => (
+                    (l / ceiling).tanh() * ceiling,
+                    (r / ceiling).tanh() * ceiling,
+                ),
+                LimiterMode::Limiter => (
+                    (l * gain).clamp(-ceiling, ceiling),
+                    (r * gain).clamp(-ceiling, ceiling),
+                ),
+            };
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{
+    bitcrusher::Bitcrusher,
+    delay::Delay,
+    eq::ParametricEq,
+    modulation::{Modulation, CHORUS, FLANGER, PHASER},
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+    reverb::Reverb,
+};
+
+/// Number of slots in the effects rack.
+pub const SLOTS: usize = 8;
+
+choice! {
+    pub enum EffectType {
+        #[default]
+        Empty => "Empty",
+        Delay => "Delay",
+        Reverb => "Reverb",
+        Chorus => "Chorus",
+        Flanger => "Flanger",
+        Phaser => "Phaser",
+        Equalizer => "EQ",
+        Bitcrusher => "Bitcrusher",
+    }
+}
+
+choice! {
+    pub enum Bypass {
+        #[default]
+        Off => "Off",
+        On => "On",
+    }
+}
+
+impl EffectType {
+    fn effect(self, sample_rate: f32) -> Option<Box<dyn Effect + Send>> {
+        match self {
+            EffectType::Empty => None,
+            EffectType::Delay => Some(Box::new(Delay::new(sample_rate))),
+            EffectType::Reverb => Some(Box::new(Reverb::new(sample_rate))),
+            EffectType::Chorus => {
+                Some(Box::new(Modulation::<CHORUS>::new(sample_rate, |params| {
+                    &params.chorus
+                })))
+            }
+            EffectType::Flanger => Some(Box::new(Modulation::<FLANGER>::new(
+                sample_rate,
+                |params| &params.flanger,
+            ))),
+            EffectType::Phaser => {
+                Some(Box::new(Modulation::<PHASER>::new(sample_rate, |params| {
+                    &params.phaser
+                })))
+            }
+            EffectType::Equalizer => Some(Box::new(ParametricEq::new(sample_rate))),
+            EffectType::Bitcrusher => Some(Box::new(Bitcrusher::new(sample_rate))),
+        }
+    }
+
+    /// Mix a slot is given when this effect is put in it. Effects that are usually blended
+    /// with the dry signal start out partly wet.
+    pub fn default_mix(self) -> f32 {
+        match self {
+            EffectType::Delay => 0.3,
+            EffectType::Reverb => 0.25,
+            EffectType::Chorus | EffectType::Flanger | EffectType::Phaser => 0.5,
+            _ => 1.0,
+        }
+    }
+}
+
+/// An effect in the rack, processing the summed output of all voices.
+pub trait Effect {
+    /// Processes a block of audio in place, with `tempo` in beats per minute.
+    fn process(&mut self, params: &Parameters, tempo: f64, left: &mut [f32], right: &mut [f32]);
+
+    /// How long the effect keeps sounding after its input goes silent, in seconds.
+    fn tail(&self, _params: &Parameters, _tempo: f64) -> f32 {
+        0.0
+    }
+}
+
+#[derive(Clone)]
+pub struct SlotParams {
+    pub effect: EffectType,
+    pub bypass: Bypass,
+    /// Balance between the input of the slot and the output of its effect. This is the only
+    /// wet/dry control; the effects themselves always output their wet signal.
+    pub mix: f32,
+}
+
+impl Default for SlotParams {
+    fn default() -> Self {
+        Self {
+            effect: EffectType::Empty,
+            bypass: Bypass::Off,
+            mix: 1.0,
+        }
+    }
+}
+
+impl SlotParams {
+    const COUNT: u32 = 3;
+
+    fn spec(slot: u32, index: u32) -> Option<ParamSpec> {
+        let module = format!("Effects/Slot {}", slot + 1);
+        let name = |name: &str| format!("Slot {} {}", slot + 1, name);
+        match index {
+            0 => Some(ParamSpec::choice(name("Effect"), module, EffectType::NAMES)),
+            1 => Some(ParamSpec::choice(name("Bypass"), module, Bypass::NAMES)),
+            2 => Some(ParamSpec::new(name("Mix"), module, 0.0, 1.0, Unit::Percent)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.effect.value()),
+            1 => Some(self.bypass.value()),
+            2 => Some(self.mix),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.effect = EffectType::from_value(value),
+            1 => self.bypass = Bypass::from_value(value),
+            2 => self.mix = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+}
+
+/// The order of the effects after the voices. The settings of each effect live in its own
+/// group, so an effect can only be placed in one slot. Later slots with the same effect are
+/// skipped.
+#[derive(Clone, Default)]
+pub struct EffectsParams {
+    pub slot: [SlotParams; SLOTS],
+}
+
+impl EffectsParams {
+    /// Whether any slot holds an effect that is switched on.
+    pub fn is_active(&self) -> bool {
+        self.chain().any(|slot| slot.effect != EffectType::Empty)
+    }
+
+    /// The slots in the order they are run, leaving out bypassed slots and repeated effects.
+    fn chain(&self) -> impl Iterator<Item = &SlotParams> {
+        let mut used = [false; EffectType::ALL.len()];
+        self.slot.iter().filter(move |slot| {
+            slot.bypass == Bypass::Off && !std::mem::replace(&mut used[slot.effect as usize], true)
+        })
+    }
+}
+
+impl ParamGroup for EffectsParams {
+    const COUNT: u32 = SLOTS as u32 * SlotParams::COUNT;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        if index >= Self::COUNT {
+            return None;
+        }
+        SlotParams::spec(index / SlotParams::COUNT, index % SlotParams::COUNT)
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        self.slot
+            .get((index / SlotParams::COUNT) as usize)?
+            .get(index % SlotParams::COUNT)
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        if let Some(slot) = self.slot.get_mut((index / SlotParams::COUNT) as usize) {
+            slot.set(index % SlotParams::COUNT, value)
+        }
+    }
+}
+
+/// Runs the effects in the order of the slots. One instance of every effect is created up
+/// front, so that moving effects around never allocates on the audio thread.
+pub struct Rack {
+    sample_rate: f32,
+    effects: Vec<Option<Box<dyn Effect + Send>>>,
+    /// Copy of the input of the current effect, for mixing it back in.
+    dry: [Vec<f32>; 2],
+}
+
+impl Rack {
+    pub fn new(sample_rate: f32, max_frames: usize) -> Self {
+        Self {
+            sample_rate,
+            effects: EffectType::ALL
+                .iter()
+                .map(|effect| effect.effect(sample_rate))
+                .collect(),
+            dry: [vec![0.0; max_frames], vec![0.0; max_frames]],
+        }
+    }
+
+    /// Length of the combined tail of all effects in the chain, in samples.
+    pub fn tail(&self, params: &Parameters, tempo: f64) -> u32 {
+        let seconds: f32 = params
+            .effects
+            .chain()
+            .filter_map(|slot| self.effects[slot.effect as usize].as_ref())
+            .map(|effect| effect.tail(params, tempo))
+            .sum();
+        (seconds * self.sample_rate) as u32
+    }
+
+    pub fn process(
+        &mut self,
+        params: &Parameters,
+        tempo: f64,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        for slot in params.effects.chain() {
+            let Some(effect) = self.effects[slot.effect as usize].as_mut() else {
+                continue;
+            };
+
+            if slot.mix >= 1.0 {
+                effect.process(params, tempo, left, right);
+                continue;
+            }
+
+            let [dry_left, dry_right] = &mut self.dry;
+            let (dry_left, dry_right) =
+                (&mut dry_left[..left.len()], &mut dry_right[..right.len()]);
+            dry_left.copy_from_slice(left);
+            dry_right.copy_from_slice(right);
+            effect.process(params, tempo, left, right);
+            for (output, dry) in left
+                .iter_mut()
+                .zip(dry_left.iter())
+                .chain(right.iter_mut().zip(dry_right.iter()))
+            {
+                *output = dry + (*output - dry) * slot.mix;
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use clack_plugin::events::event_types::ParamValueEvent;
+use crate::params::{ParamGroup, ParamSpec, Unit};
 
 #[derive(Clone)]
 pub struct Envelope {
@@ -20,12 +20,49 @@ impl Default for Envelope {
 }
 
 impl Envelope {
-    pub fn handle_event(&mut self, event: &ParamValueEvent) {
-        match event.param_id().map(|x| x.into()) {
-            Some(0) => self.attack = event.value() as f32,
-            Some(1) => self.decay = event.value() as f32,
-            Some(2) => self.sustain = event.value() as f32,
-            Some(3) => self.release = event.value() as f32,
+    /// Describes the envelope parameter at `index`, prefixing the name for envelopes that are
+    /// nested in other parameter groups.
+    pub fn spec_with(prefix: &str, module: &str, index: u32) -> Option<ParamSpec> {
+        let (name, unit) = match index {
+            0 => ("Attack", Unit::Seconds),
+            1 => ("Decay", Unit::Seconds),
+            2 => ("Sustain", Unit::Percent),
+            3 => ("Release", Unit::Seconds),
+            _ => return None,
+        };
+        Some(ParamSpec::new(
+            format!("{prefix}{name}"),
+            module,
+            0.0,
+            1.0,
+            unit,
+        ))
+    }
+}
+
+impl ParamGroup for Envelope {
+    const COUNT: u32 = 4;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        Self::spec_with("", "", index)
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.attack),
+            1 => Some(self.decay),
+            2 => Some(self.sustain),
+            3 => Some(self.release),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.attack = value,
+            1 => self.decay = value,
+            2 => self.sustain = value,
+            3 => self.release = value,
             _ => {}
         }
     }
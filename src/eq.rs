@@ -0,0 +1,344 @@
+use std::f32::consts::{FRAC_1_SQRT_2, TAU};
+
+use crate::{
+    effects::Effect,
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+/// Number of biquads run in series: two for each cut filter and one for every band.
+const STAGES: usize = 8;
+/// Quality factors of the two halves of a fourth order Butterworth filter.
+pub const BUTTERWORTH: [f32; 2] = [0.5412, 1.3066];
+
+choice! {
+    pub enum Slope {
+        #[default]
+        Off => "Off",
+        Gentle => "12 dB/oct",
+        Steep => "24 dB/oct",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Shape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// Names and shapes of the bands, from low to high.
+const BANDS: [(&str, Shape); 4] = [
+    ("Low Shelf", Shape::LowShelf),
+    ("Peak 1", Shape::Peak),
+    ("Peak 2", Shape::Peak),
+    ("High Shelf", Shape::HighShelf),
+];
+
+#[derive(Clone, PartialEq)]
+pub struct BandParams {
+    /// Centre or corner frequency, in Hz.
+    pub frequency: f32,
+    /// Boost or cut, in dB.
+    pub gain: f32,
+    /// Width of a peak, or steepness of a shelf.
+    pub q: f32,
+}
+
+impl BandParams {
+    const COUNT: u32 = 3;
+
+    fn spec(band: u32, index: u32) -> Option<ParamSpec> {
+        let band = BANDS[band as usize].0;
+        let module = format!("EQ/{band}");
+        let name = |name: &str| format!("EQ {band} {name}");
+        match index {
+            0 => Some(ParamSpec::new(
+                name("Frequency"),
+                module,
+                20.0,
+                20000.0,
+                Unit::Hertz,
+            )),
+            1 => Some(ParamSpec::new(
+                name("Gain"),
+                module,
+                -18.0,
+                18.0,
+                Unit::Decibels,
+            )),
+            2 => Some(ParamSpec::new(name("Q"), module, 0.1, 10.0, Unit::Ratio)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.frequency),
+            1 => Some(self.gain),
+            2 => Some(self.q),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.frequency = value,
+            1 => self.gain = value,
+            2 => self.q = value,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct EqParams {
+    pub low_cut: Slope,
+    /// Cutoff of the highpass filter, in Hz.
+    pub low_cut_frequency: f32,
+    pub high_cut: Slope,
+    /// Cutoff of the lowpass filter, in Hz.
+    pub high_cut_frequency: f32,
+    pub band: [BandParams; 4],
+}
+
+impl Default for EqParams {
+    fn default() -> Self {
+        let band = |frequency| BandParams {
+            frequency,
+            gain: 0.0,
+            q: FRAC_1_SQRT_2,
+        };
+        Self {
+            low_cut: Slope::Off,
+            low_cut_frequency: 30.0,
+            high_cut: Slope::Off,
+            high_cut_frequency: 18000.0,
+            band: [band(100.0), band(500.0), band(2500.0), band(8000.0)],
+        }
+    }
+}
+
+impl EqParams {
+    /// Coefficients of every stage of the EQ at the given sample rate.
+    pub fn coefficients(&self, sample_rate: f32) -> [Coefficients; STAGES] {
+        let mut stages = [Coefficients::IDENTITY; STAGES];
+        let cuts = [
+            (self.low_cut, self.low_cut_frequency, true),
+            (self.high_cut, self.high_cut_frequency, false),
+        ];
+        for (stages, (slope, frequency, highpass)) in stages.chunks_mut(2).zip(cuts) {
+            let qs: &[f32] = match slope {
+                Slope::Off => &[],
+                Slope::Gentle => &[FRAC_1_SQRT_2],
+                Slope::Steep => &BUTTERWORTH,
+            };
+            for (stage, &q) in stages.iter_mut().zip(qs) {
+                *stage = Coefficients::pass(highpass, frequency, q, sample_rate);
+            }
+        }
+        for (stage, (band, (_, shape))) in stages[4..].iter_mut().zip(self.band.iter().zip(BANDS)) {
+            if band.gain != 0.0 {
+                *stage = Coefficients::band(shape, band, sample_rate);
+            }
+        }
+        stages
+    }
+}
+
+impl ParamGroup for EqParams {
+    const COUNT: u32 = 4 + BANDS.len() as u32 * BandParams::COUNT;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("EQ Low Cut", "EQ", Slope::NAMES)),
+            1 => Some(ParamSpec::new(
+                "EQ Low Cut Frequency",
+                "EQ",
+                20.0,
+                2000.0,
+                Unit::Hertz,
+            )),
+            2 => Some(ParamSpec::choice("EQ High Cut", "EQ", Slope::NAMES)),
+            3 => Some(ParamSpec::new(
+                "EQ High Cut Frequency",
+                "EQ",
+                1000.0,
+                20000.0,
+                Unit::Hertz,
+            )),
+            index if index < Self::COUNT => BandParams::spec(
+                (index - 4) / BandParams::COUNT,
+                (index - 4) % BandParams::COUNT,
+            ),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.low_cut.value()),
+            1 => Some(self.low_cut_frequency),
+            2 => Some(self.high_cut.value()),
+            3 => Some(self.high_cut_frequency),
+            index => self
+                .band
+                .get(((index - 4) / BandParams::COUNT) as usize)?
+                .get((index - 4) % BandParams::COUNT),
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.low_cut = Slope::from_value(value),
+            1 => self.low_cut_frequency = value,
+            2 => self.high_cut = Slope::from_value(value),
+            3 => self.high_cut_frequency = value,
+            index => {
+                if let Some(band) = self
+                    .band
+                    .get_mut(((index - 4) / BandParams::COUNT) as usize)
+                {
+                    band.set((index - 4) % BandParams::COUNT, value)
+                }
+            }
+        }
+    }
+}
+
+/// Normalised coefficients of a biquad filter, following the Audio EQ Cookbook.
+#[derive(Clone, Copy)]
+pub struct Coefficients {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Coefficients {
+    const IDENTITY: Self = Self {
+        b: [1.0, 0.0, 0.0],
+        a: [0.0, 0.0],
+    };
+
+    fn new(b: [f32; 3], a: [f32; 3]) -> Self {
+        Self {
+            b: b.map(|b| b / a[0]),
+            a: [a[1] / a[0], a[2] / a[0]],
+        }
+    }
+
+    pub fn pass(highpass: bool, frequency: f32, q: f32, sample_rate: f32) -> Self {
+        let w = TAU * frequency.min(sample_rate * 0.49) / sample_rate;
+        let (sin, cos) = w.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a = [1.0 + alpha, -2.0 * cos, 1.0 - alpha];
+        if highpass {
+            Self::new([(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0], a)
+        } else {
+            Self::new([(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0], a)
+        }
+    }
+
+    fn band(shape: Shape, band: &BandParams, sample_rate: f32) -> Self {
+        let w = TAU * band.frequency.min(sample_rate * 0.49) / sample_rate;
+        let (sin, cos) = w.sin_cos();
+        let alpha = sin / (2.0 * band.q);
+        let a = 10f32.powf(band.gain / 40.0);
+        let root = 2.0 * a.sqrt() * alpha;
+        match shape {
+            Shape::Peak => Self::new(
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            Shape::LowShelf => Self::new(
+                [
+                    a * ((a + 1.0) - (a - 1.0) * cos + root),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - root),
+                ],
+                [
+                    (a + 1.0) + (a - 1.0) * cos + root,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - root,
+                ],
+            ),
+            Shape::HighShelf => Self::new(
+                [
+                    a * ((a + 1.0) + (a - 1.0) * cos + root),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - root),
+                ],
+                [
+                    (a + 1.0) - (a - 1.0) * cos + root,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - root,
+                ],
+            ),
+        }
+    }
+
+    /// Magnitude of the response at `frequency`, as a linear gain.
+    pub fn magnitude(&self, frequency: f32, sample_rate: f32) -> f32 {
+        let w = TAU * frequency / sample_rate;
+        // Evaluate both polynomials at z = e^-jw
+        let evaluate = |c: [f32; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        evaluate(self.b) / evaluate([1.0, self.a[0], self.a[1]])
+    }
+}
+
+/// Transposed direct form II state of one biquad.
+#[derive(Clone, Copy, Default)]
+pub struct Biquad {
+    s1: f32,
+    s2: f32,
+}
+
+impl Biquad {
+    pub fn process(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b[0] * x + self.s1;
+        self.s1 = c.b[1] * x - c.a[0] * y + self.s2;
+        self.s2 = c.b[2] * x - c.a[1] * y;
+        y
+    }
+}
+
+/// Parametric EQ with four bands between a highpass and a lowpass. The coefficients are only
+/// recalculated at the start of a block, when the settings have changed.
+pub struct ParametricEq {
+    sample_rate: f32,
+    /// Settings the coefficients were last calculated for.
+    settings: Option<EqParams>,
+    coefficients: [Coefficients; STAGES],
+    biquads: [[Biquad; STAGES]; 2],
+}
+
+impl ParametricEq {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            settings: None,
+            coefficients: [Coefficients::IDENTITY; STAGES],
+            biquads: [[Biquad::default(); STAGES]; 2],
+        }
+    }
+}
+
+impl Effect for ParametricEq {
+    fn process(&mut self, params: &Parameters, _tempo: f64, left: &mut [f32], right: &mut [f32]) {
+        if self.settings.as_ref() != Some(&params.eq) {
+            self.coefficients = params.eq.coefficients(self.sample_rate);
+            self.settings = Some(params.eq.clone());
+        }
+
+        for (biquads, samples) in self.biquads.iter_mut().zip([left, right]) {
+            for sample in samples.iter_mut() {
+                *sample = biquads
+                    .iter_mut()
+                    .zip(&self.coefficients)
+                    .fold(*sample, |x, (biquad, c)| biquad.process(c, x));
+            }
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::f32::consts::PI;
+
+use crate::{
+    oversampling::Oversampler,
+    params::{choice, ParamGroup, ParamSpec, Unit},
+};
+
+choice! {
+    pub enum FilterType {
+        #[default]
+        Off => "Off",
+        LowPass => "SVF Low-pass",
+        HighPass => "SVF High-pass",
+        BandPass => "SVF Band-pass",
+        Notch => "SVF Notch",
+        Ladder => "Ladder",
+        Formant => "Formant",
+    }
+}
+
+choice! {
+    pub enum Slope {
+        #[default]
+        Poles4 => "24 dB",
+        Poles2 => "12 dB",
+    }
+}
+
+#[derive(Clone)]
+pub struct SlotParams {
+    pub filter_type: FilterType,
+    pub cutoff: f32,
+    /// Offset applied to `cutoff` by host modulation, which is not part of the saved state.
+    pub cutoff_modulation: f32,
+    pub resonance: f32,
+    /// Gain into the ladder, in dB.
+    pub drive: f32,
+    pub slope: Slope,
+    /// Position of the formant filter between the vowels A, E, I, O and U.
+    pub vowel: f32,
+    /// Offset applied to `vowel` by host modulation, which is not part of the saved state.
+    pub vowel_modulation: f32,
+}
+
+impl Default for SlotParams {
+    fn default() -> Self {
+        Self {
+            filter_type: FilterType::Off,
+            cutoff: 2000.0,
+            cutoff_modulation: 0.0,
+            resonance: 0.2,
+            drive: 0.0,
+            slope: Slope::Poles4,
+            vowel: 0.0,
+            vowel_modulation: 0.0,
+        }
+    }
+}
+
+impl SlotParams {
+    pub fn cutoff(&self, lane: Lane) -> f32 {
+        ((self.cutoff + self.cutoff_modulation) * lane.cutoff).clamp(20.0, 20000.0)
+    }
+
+    pub fn vowel(&self, lane: Lane) -> f32 {
+        (self.vowel + self.vowel_modulation + lane.vowel).clamp(0.0, 1.0)
+    }
+
+    const COUNT: u32 = 6;
+
+    fn spec(slot: u32, index: u32) -> Option<ParamSpec> {
+        let module = format!("Filter/Filter {}", slot + 1);
+        let name = |name: &str| format!("Filter {} {}", slot + 1, name);
+        match index {
+            0 => Some(ParamSpec::choice(name("Type"), module, FilterType::NAMES)),
+            1 => Some(
+                ParamSpec::new(name("Cutoff"), module, 20.0, 20000.0, Unit::Hertz).modulatable(),
+            ),
+            2 => Some(ParamSpec::new(
+                name("Resonance"),
+                module,
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            3 => Some(ParamSpec::new(
+                name("Drive"),
+                module,
+                0.0,
+                24.0,
+                Unit::Decibels,
+            )),
+            4 => Some(ParamSpec::choice(name("Slope"), module, Slope::NAMES)),
+            5 => Some(ParamSpec::new(name("Vowel"), module, 0.0, 1.0, Unit::Percent).modulatable()),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.filter_type.value()),
+            1 => Some(self.cutoff),
+            2 => Some(self.resonance),
+            3 => Some(self.drive),
+            4 => Some(self.slope.value()),
+            5 => Some(self.vowel),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.filter_type = FilterType::from_value(value),
+            1 => self.cutoff = value,
+            2 => self.resonance = value,
+            3 => self.drive = value,
+            4 => self.slope = Slope::from_value(value),
+            5 => self.vowel = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    fn modulate(&mut self, index: u32, amount: f32) {
+        match index {
+            1 => self.cutoff_modulation = amount,
+            5 => self.vowel_modulation = amount,
+            _ => {}
+        }
+    }
+}
+
+/// Where the modulation lane of the sequencer moves the filters. It changes from step to step
+/// while playing, so it is handed to the voices rather than kept in the parameters.
+#[derive(Clone, Copy)]
+pub struct Lane {
+    /// Factor applied to the cutoff.
+    pub cutoff: f32,
+    /// Offset applied to the vowel.
+    pub vowel: f32,
+}
+
+impl Default for Lane {
+    fn default() -> Self {
+        Self {
+            cutoff: 1.0,
+            vowel: 0.0,
+        }
+    }
+}
+
+choice! {
+    pub enum Routing {
+        #[default]
+        Serial => "Serial",
+        Parallel => "Parallel",
+        Split => "Stereo Split",
+    }
+}
+
+#[derive(Clone)]
+pub struct FilterParams {
+    pub routing: Routing,
+    /// Weighting of the two slots, from only the first at 0 to only the second at 1. In the
+    /// middle both are at full level, except in parallel where they are mixed half and half.
+    pub balance: f32,
+    pub slot: [SlotParams; 2],
+}
+
+impl Default for FilterParams {
+    fn default() -> Self {
+        Self {
+            routing: Routing::Serial,
+            balance: 0.5,
+            slot: Default::default(),
+        }
+    }
+}
+
+impl FilterParams {
+    /// Levels of the two slots, as set by the balance.
+    fn levels(&self) -> (f32, f32) {
+        (
+            (2.0 - 2.0 * self.balance).min(1.0),
+            (2.0 * self.balance).min(1.0),
+        )
+    }
+}
+
+/// Index of the routing parameter. The first slot keeps the ids the filter had before it
+/// got a second one, so the shared parameters and the second slot come after it.
+const ROUTING: u32 = SlotParams::COUNT;
+const BALANCE: u32 = SlotParams::COUNT + 1;
+
+/// The slot a parameter belongs to, and its index within that slot.
+fn slot_index(index: u32) -> Option<(usize, u32)> {
+    match index {
+        _ if index < ROUTING => Some((0, index)),
+        ROUTING | BALANCE => None,
+        _ if index < FilterParams::COUNT => Some((1, index - BALANCE - 1)),
+        _ => None,
+    }
+}
+
+impl ParamGroup for FilterParams {
+    const COUNT: u32 = 2 + 2 * SlotParams::COUNT;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            ROUTING => Some(ParamSpec::choice("Routing", "Filter", Routing::NAMES)),
+            BALANCE => Some(ParamSpec::new("Balance", "Filter", 0.0, 1.0, Unit::Percent)),
+            _ => slot_index(index).and_then(|(slot, index)| SlotParams::spec(slot as u32, index)),
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            ROUTING => Some(self.routing.value()),
+            BALANCE => Some(self.balance),
+            _ => slot_index(index).and_then(|(slot, index)| self.slot[slot].get(index)),
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            ROUTING => self.routing = Routing::from_value(value),
+            BALANCE => self.balance = value.clamp(0.0, 1.0),
+            _ => {
+                if let Some((slot, index)) = slot_index(index) {
+                    self.slot[slot].set(index, value)
+                }
+            }
+        }
+    }
+
+    fn modulate(&mut self, index: u32, amount: f32) {
+        if let Some((slot, index)) = slot_index(index) {
+            self.slot[slot].modulate(index, amount)
+        }
+    }
+}
+
+/// Frequency, level in dB and bandwidth of the first three formants of each vowel, sung by a
+/// bass voice.
+const VOWELS: [[(f32, f32, f32); 3]; 5] = [
+    [
+        (600.0, 0.0, 60.0),
+        (1040.0, -7.0, 70.0),
+        (2250.0, -9.0, 110.0),
+    ],
+    [
+        (400.0, 0.0, 40.0),
+        (1620.0, -12.0, 80.0),
+        (2400.0, -9.0, 100.0),
+    ],
+    [
+        (250.0, 0.0, 60.0),
+        (1750.0, -30.0, 90.0),
+        (2600.0, -16.0, 100.0),
+    ],
+    [
+        (400.0, 0.0, 40.0),
+        (750.0, -11.0, 80.0),
+        (2400.0, -21.0, 100.0),
+    ],
+    [
+        (350.0, 0.0, 40.0),
+        (600.0, -20.0, 80.0),
+        (2400.0, -32.0, 100.0),
+    ],
+];
+
+/// Cheap approximation of `tanh`, accurate to about 2% and exactly 1 from 3 onwards.
+fn saturate(x: f32) -> f32 {
+    let x = x.clamp(-3.0, 3.0);
+    x * (27.0 + x * x) / (27.0 + 9.0 * x * x)
+}
+
+/// State variable filter in the topology-preserving form described by Andrew Simper, which
+/// stays stable while the cutoff is modulated.
+#[derive(Clone, Default)]
+struct Svf {
+    ic1: f32,
+    ic2: f32,
+}
+
+impl Svf {
+    /// Coefficients for a cutoff in Hz, with the damping `k` running from 2 for no resonance
+    /// down towards 0.
+    fn coefficients(cutoff: f32, k: f32, sample_rate: f32) -> (f32, f32, f32, f32) {
+        let g = (PI * cutoff.min(sample_rate * 0.49) / sample_rate).tan();
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        (a1, g * a1, g * g * a1, k)
+    }
+
+    fn process(&mut self, (a1, a2, a3, k): (f32, f32, f32, f32), mode: FilterType, x: f32) -> f32 {
+        let v3 = x - self.ic2;
+        let v1 = a1 * self.ic1 + a2 * v3;
+        let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+
+        match mode {
+            FilterType::HighPass => x - k * v1 - v2,
+            FilterType::BandPass => v1,
+            FilterType::Notch => x - k * v1,
+            _ => v2,
+        }
+    }
+}
+
+/// Four one-pole stages in a feedback loop, solved without a delay in the feedback path so
+/// that resonance and tuning hold up at high cutoffs. The input transistors saturate, which
+/// limits the level when the filter oscillates.
+#[derive(Clone, Default)]
+struct Ladder {
+    stages: [f32; 4],
+    oversampler: Oversampler,
+}
+
+impl Ladder {
+    fn process(&mut self, g: f32, feedback: f32, drive: f32, slope: Slope, x: f32) -> f32 {
+        let stages = &mut self.stages;
+        let gain = g / (1.0 + g);
+        self.oversampler.process(1, x, |x| {
+            // Output of the last stage without the input, to solve the feedback loop with
+            let state = stages
+                .iter()
+                .fold(0.0, |sum, stage| sum * gain + stage / (1.0 + g));
+            let gain4 = gain * gain * gain * gain;
+            let mut y = saturate((x * drive - feedback * state) / (1.0 + feedback * gain4));
+
+            let mut outputs = [0.0; 4];
+            for (stage, output) in stages.iter_mut().zip(&mut outputs) {
+                let v = (y - *stage) * gain;
+                y = v + *stage;
+                *stage = y + v;
+                *output = y;
+            }
+            match slope {
+                Slope::Poles4 => outputs[3],
+                Slope::Poles2 => outputs[1],
+            }
+        })
+    }
+}
+
+/// State of one filter slot. Coefficients are only recalculated when the parameters change.
+#[derive(Clone, Default)]
+struct Slot {
+    svf: Svf,
+    ladder: Ladder,
+    /// Parallel band-passes, one for each formant.
+    formants: [Svf; 3],
+    /// Cutoff, resonance and vowel the coefficients were calculated for.
+    settings: (f32, f32, f32),
+    svf_coefficients: (f32, f32, f32, f32),
+    ladder_coefficient: f32,
+    formant_coefficients: [(f32, f32, f32, f32); 3],
+    formant_gains: [f32; 3],
+}
+
+impl Slot {
+    fn reset(&mut self) {
+        self.svf = Svf::default();
+        self.formants = Default::default();
+        self.ladder.stages = [0.0; 4];
+        self.ladder.oversampler.reset();
+    }
+
+    fn process(&mut self, params: &SlotParams, lane: Lane, sample_rate: f32, x: f32) -> f32 {
+        if params.filter_type == FilterType::Off {
+            return x;
+        }
+
+        let settings = (params.cutoff(lane), params.resonance, params.vowel(lane));
+        if settings != self.settings {
+            self.settings = settings;
+            self.update(params, sample_rate);
+        }
+
+        match params.filter_type {
+            FilterType::Off => x,
+            FilterType::Ladder => {
+                // Slightly above 4 so that the ladder oscillates on its own at full resonance
+                let feedback = params.resonance * 4.2;
+                let drive = 10f32.powf(params.drive / 20.0);
+                // The passband drops as resonance goes up, which is partly made up for
+                let output =
+                    self.ladder
+                        .process(self.ladder_coefficient, feedback, drive, params.slope, x);
+                output * (1.0 + params.resonance)
+            }
+            FilterType::Formant => self
+                .formants
+                .iter_mut()
+                .zip(&self.formant_coefficients)
+                .zip(&self.formant_gains)
+                .map(|((formant, &coefficients), gain)| {
+                    // Scaled by `k` for unity gain at the peak of each band
+                    formant.process(coefficients, FilterType::BandPass, x) * coefficients.3 * gain
+                })
+                .sum(),
+            mode => self.svf.process(self.svf_coefficients, mode, x),
+        }
+    }
+
+    fn update(&mut self, params: &SlotParams, sample_rate: f32) {
+        let (cutoff, _, vowel) = self.settings;
+        let cutoff = cutoff.min(sample_rate * 0.49);
+        let k = 2.0 - 2.0 * params.resonance.clamp(0.0, 0.99);
+        self.svf_coefficients = Svf::coefficients(cutoff, k, sample_rate);
+
+        // The ladder runs at twice the sample rate
+        self.ladder_coefficient = (PI * cutoff / (2.0 * sample_rate)).tan();
+
+        // Morph between neighbouring vowels, with resonance narrowing the formants
+        let position = vowel * (VOWELS.len() - 1) as f32;
+        let index = (position as usize).min(VOWELS.len() - 2);
+        let t = position - index as f32;
+        let narrowing = 1.0 - 0.75 * params.resonance;
+        for (formant, (from, to)) in VOWELS[index].iter().zip(&VOWELS[index + 1]).enumerate() {
+            let mix = |a: f32, b: f32| a + (b - a) * t;
+            let frequency = mix(from.0, to.0);
+            let bandwidth = mix(from.2, to.2) * narrowing;
+            self.formant_coefficients[formant] =
+                Svf::coefficients(frequency, bandwidth / frequency, sample_rate);
+            // The narrow bands let little through, which 12 dB of gain makes up for
+            self.formant_gains[formant] = 10f32.powf((mix(from.1, to.1) + 12.0) / 20.0);
+        }
+    }
+}
+
+/// The filter slots of a voice, routed into a stereo pair.
+#[derive(Clone, Default)]
+pub struct Filter {
+    slots: [Slot; 2],
+}
+
+impl Filter {
+    pub fn reset(&mut self) {
+        self.slots.iter_mut().for_each(Slot::reset);
+    }
+
+    pub fn process(
+        &mut self,
+        params: &FilterParams,
+        lane: Lane,
+        sample_rate: f32,
+        x: f32,
+    ) -> (f32, f32) {
+        let [first, second] = &mut self.slots;
+        let [first_params, second_params] = &params.slot;
+        let (first_level, second_level) = params.levels();
+
+        match params.routing {
+            Routing::Serial => {
+                // Fading out a slot bypasses it rather than muting the chain
+                let y = first.process(first_params, lane, sample_rate, x);
+                let y = x + (y - x) * first_level;
+                let z = second.process(second_params, lane, sample_rate, y);
+                let z = y + (z - y) * second_level;
+                (z, z)
+            }
+            Routing::Parallel => {
+                // Crossfading keeps the sum at the level of a single slot
+                let y = first.process(first_params, lane, sample_rate, x) * (1.0 - params.balance)
+                    + second.process(second_params, lane, sample_rate, x) * params.balance;
+                (y, y)
+            }
+            Routing::Split => (
+                first.process(first_params, lane, sample_rate, x) * first_level,
+                second.process(second_params, lane, sample_rate, x) * second_level,
+            ),
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    adsr::ADSR,
+    envelope::Envelope,
+    oscillator::Generator,
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+/// Phase offset, in cycles, caused by a modulator playing at full level.
+const MODULATION_DEPTH: f32 = 1.0;
+
+/// Routing between the operators of a voice. Operator `n` may only be modulated by operators
+/// with a higher index, which lets a voice be computed in a single pass from the last operator
+/// down to the first.
+pub struct Algorithm {
+    /// For every operator, a bit mask of the operators that modulate it.
+    pub modulators: [u8; 6],
+    /// Bit mask of the operators that are mixed into the output.
+    pub carriers: u8,
+}
+
+const fn algorithm(modulators: [u8; 6], carriers: u8) -> Algorithm {
+    Algorithm {
+        modulators,
+        carriers,
+    }
+}
+
+/// The eight algorithms of 4-operator synthesizers such as the DX21 and TX81Z.
+pub const ALGORITHMS_4OP: [Algorithm; 8] = [
+    algorithm([0b10, 0b100, 0b1000, 0, 0, 0], 0b1),
+    algorithm([0b10, 0b1100, 0, 0, 0, 0], 0b1),
+    algorithm([0b1010, 0b100, 0, 0, 0, 0], 0b1),
+    algorithm([0b110, 0, 0b1000, 0, 0, 0], 0b1),
+    algorithm([0b10, 0, 0b1000, 0, 0, 0], 0b101),
+    algorithm([0b1000, 0b1000, 0b1000, 0, 0, 0], 0b111),
+    algorithm([0, 0, 0b1000, 0, 0, 0], 0b111),
+    algorithm([0, 0, 0, 0, 0, 0], 0b1111),
+];
+
+/// The 32 algorithms of the DX7.
+pub const ALGORITHMS_6OP: [Algorithm; 32] = [
+    algorithm([0b10, 0, 0b1000, 0b10000, 0b100000, 0], 0b101),
+    algorithm([0b10, 0, 0b1000, 0b10000, 0b100000, 0], 0b101),
+    algorithm([0b10, 0b100, 0, 0b10000, 0b100000, 0], 0b1001),
+    algorithm([0b10, 0b100, 0, 0b10000, 0b100000, 0], 0b1001),
+    algorithm([0b10, 0, 0b1000, 0, 0b100000, 0], 0b10101),
+    algorithm([0b10, 0, 0b1000, 0, 0b100000, 0], 0b10101),
+    algorithm([0b10, 0, 0b11000, 0, 0b100000, 0], 0b101),
+    algorithm([0b10, 0, 0b11000, 0, 0b100000, 0], 0b101),
+    algorithm([0b10, 0, 0b11000, 0, 0b100000, 0], 0b101),
+    algorithm([0b10, 0b100, 0, 0b110000, 0, 0], 0b1001),
+    algorithm([0b10, 0b100, 0, 0b110000, 0, 0], 0b1001),
+    algorithm([0b10, 0, 0b111000, 0, 0, 0], 0b101),
+    algorithm([0b10, 0, 0b111000, 0, 0, 0], 0b101),
+    algorithm([0b10, 0, 0b1000, 0b110000, 0, 0], 0b101),
+    algorithm([0b10, 0, 0b1000, 0b110000, 0, 0], 0b101),
+    algorithm([0b10110, 0, 0b1000, 0, 0b100000, 0], 0b1),
+    algorithm([0b10110, 0, 0b1000, 0, 0b100000, 0], 0b1),
+    algorithm([0b1110, 0, 0, 0b10000, 0b100000, 0], 0b1),
+    algorithm([0b10, 0b100, 0, 0b100000, 0b100000, 0], 0b11001),
+    algorithm([0b100, 0b100, 0, 0b110000, 0, 0], 0b1011),
+    algorithm([0b100, 0b100, 0, 0b100000, 0b100000, 0], 0b11011),
+    algorithm([0b10, 0, 0b100000, 0b100000, 0b100000, 0], 0b11101),
+    algorithm([0, 0b100, 0, 0b100000, 0b100000, 0], 0b11011),
+    algorithm([0, 0, 0b100000, 0b100000, 0b100000, 0], 0b11111),
+    algorithm([0, 0, 0, 0b100000, 0b100000, 0], 0b11111),
+    algorithm([0, 0b100, 0, 0b110000, 0, 0], 0b1011),
+    algorithm([0, 0b100, 0, 0b110000, 0, 0], 0b1011),
+    algorithm([0b10, 0, 0b1000, 0b10000, 0, 0], 0b100101),
+    algorithm([0, 0, 0b1000, 0, 0b100000, 0], 0b10111),
+    algorithm([0, 0, 0b1000, 0b10000, 0, 0], 0b100111),
+    algorithm([0, 0, 0, 0, 0b100000, 0], 0b11111),
+    algorithm([0, 0, 0, 0, 0, 0], 0b111111),
+];
+
+choice! {
+    pub enum Operators {
+        #[default]
+        Four => "4",
+        Six => "6",
+    }
+}
+
+impl Operators {
+    pub fn algorithms(self) -> &'static [Algorithm] {
+        match self {
+            Operators::Four => &ALGORITHMS_4OP,
+            Operators::Six => &ALGORITHMS_6OP,
+        }
+    }
+}
+
+choice! {
+    pub enum FrequencyMode {
+        #[default]
+        Ratio => "Ratio",
+        Fixed => "Fixed",
+    }
+}
+
+#[derive(Clone)]
+pub struct OperatorParams {
+    pub mode: FrequencyMode,
+    pub ratio: f32,
+    pub frequency: f32,
+    pub level: f32,
+    pub feedback: f32,
+    pub envelope: Envelope,
+}
+
+impl Default for OperatorParams {
+    fn default() -> Self {
+        Self {
+            mode: FrequencyMode::Ratio,
+            ratio: 1.0,
+            frequency: 440.0,
+            level: 0.0,
+            feedback: 0.0,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.5,
+                sustain: 1.0,
+                release: 0.2,
+            },
+        }
+    }
+}
+
+impl OperatorParams {
+    const COUNT: u32 = 5 + Envelope::COUNT;
+
+    fn spec(operator: u32, index: u32) -> Option<ParamSpec> {
+        let module = format!("FM/Operator {}", operator + 1);
+        let name = |name: &str| format!("Op {} {}", operator + 1, name);
+        match index {
+            0 => Some(ParamSpec::choice(
+                name("Mode"),
+                module,
+                FrequencyMode::NAMES,
+            )),
+            1 => Some(ParamSpec::new(
+                name("Ratio"),
+                module,
+                0.125,
+                32.0,
+                Unit::Ratio,
+            )),
+            2 => Some(ParamSpec::new(
+                name("Frequency"),
+                module,
+                1.0,
+                20000.0,
+                Unit::Hertz,
+            )),
+            3 => Some(ParamSpec::new(
+                name("Level"),
+                module,
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            4 => Some(ParamSpec::new(
+                name("Feedback"),
+                module,
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => Envelope::spec_with(&name(""), &module, index - 5),
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.mode.value()),
+            1 => Some(self.ratio),
+            2 => Some(self.frequency),
+            3 => Some(self.level),
+            4 => Some(self.feedback),
+            _ => self.envelope.get(index - 5),
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.mode = FrequencyMode::from_value(value),
+            1 => self.ratio = value,
+            2 => self.frequency = value,
+            3 => self.level = value,
+            4 => self.feedback = value,
+            _ => self.envelope.set(index - 5, value),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FmParams {
+    pub operators: Operators,
+    /// One-based algorithm numbers, as printed on the front panel of the classic synthesizers.
+    /// The 6-operator and 4-operator modes each have their own, since they have a different
+    /// number of algorithms.
+    pub algorithm: u32,
+    pub algorithm_4op: u32,
+    pub operator: [OperatorParams; 6],
+}
+
+impl Default for FmParams {
+    fn default() -> Self {
+        let mut operator: [OperatorParams; 6] = Default::default();
+        operator[0].level = 1.0;
+        operator[1].ratio = 2.0;
+        operator[1].level = 0.5;
+        operator[1].envelope.sustain = 0.2;
+
+        Self {
+            operators: Operators::Four,
+            algorithm: 1,
+            algorithm_4op: 1,
+            operator,
+        }
+    }
+}
+
+impl FmParams {
+    pub fn algorithm(&self) -> &'static Algorithm {
+        let algorithm = match self.operators {
+            Operators::Four => self.algorithm_4op,
+            Operators::Six => self.algorithm,
+        };
+        let algorithms = self.operators.algorithms();
+        &algorithms[(algorithm.max(1) as usize - 1).min(algorithms.len() - 1)]
+    }
+}
+
+/// The 4-operator algorithm comes after the operators, having been added later.
+const ALGORITHM_4OP: u32 = 2 + 6 * OperatorParams::COUNT;
+
+impl ParamGroup for FmParams {
+    const COUNT: u32 = ALGORITHM_4OP + 1;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Operators", "FM", Operators::NAMES)),
+            1 => Some(ParamSpec::new(
+                "6-Op Algorithm",
+                "FM",
+                1.0,
+                32.0,
+                Unit::Integer,
+            )),
+            ALGORITHM_4OP => Some(ParamSpec::new(
+                "4-Op Algorithm",
+                "FM",
+                1.0,
+                8.0,
+                Unit::Integer,
+            )),
+            _ if index < Self::COUNT => OperatorParams::spec(
+                (index - 2) / OperatorParams::COUNT,
+                (index - 2) % OperatorParams::COUNT,
+            ),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.operators.value()),
+            1 => Some(self.algorithm as f32),
+            ALGORITHM_4OP => Some(self.algorithm_4op as f32),
+            _ => self
+                .operator
+                .get(((index - 2) / OperatorParams::COUNT) as usize)?
+                .get((index - 2) % OperatorParams::COUNT),
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.operators = Operators::from_value(value),
+            1 => self.algorithm = value.round().clamp(1.0, 32.0) as u32,
+            ALGORITHM_4OP => self.algorithm_4op = value.round().clamp(1.0, 8.0) as u32,
+            _ => {
+                if let Some(operator) = self
+                    .operator
+                    .get_mut(((index - 2) / OperatorParams::COUNT) as usize)
+                {
+                    operator.set((index - 2) % OperatorParams::COUNT, value)
+                }
+            }
+        }
+    }
+}
+
+/// A voice of phase-modulation operators, each with its own envelope.
+#[derive(Default)]
+pub struct FmVoice {
+    phases: [f32; 6],
+    previous: [f32; 6],
+    feedback: [f32; 6],
+    envelopes: [ADSR; 6],
+}
+
+impl Generator for FmVoice {
+    fn start(&mut self, params: &Parameters, _key: u16, _velocity: f32) {
+        self.phases = [0.0; 6];
+        self.previous = [0.0; 6];
+        self.feedback = [0.0; 6];
+        for (envelope, operator) in self.envelopes.iter_mut().zip(&params.fm.operator) {
+            *envelope = ADSR::new(operator.envelope.clone());
+        }
+    }
+
+    fn release(&mut self) {
+        self.envelopes.iter_mut().for_each(ADSR::release);
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let fm = &params.fm;
+        let algorithm = fm.algorithm();
+        let count = match fm.operators {
+            Operators::Four => 4,
+            Operators::Six => 6,
+        };
+
+        let mut outputs = [0.0; 6];
+        let mut sample = 0.0;
+        for op in (0..count).rev() {
+            let operator = &fm.operator[op];
+            let modulation = (op + 1..count)
+                .filter(|modulator| algorithm.modulators[op] & (1 << modulator) != 0)
+                .map(|modulator| outputs[modulator])
+                .sum::<f32>()
+                + self.feedback[op] * operator.feedback;
+
+            let phase = self.phases[op] + modulation * MODULATION_DEPTH;
+            let output =
+                (phase * TAU).sin() * operator.level * self.envelopes[op].process(sample_rate);
+
+            // Averaging the last two outputs keeps high feedback amounts from turning into noise
+            self.feedback[op] = (self.previous[op] + output) * 0.5;
+            self.previous[op] = output;
+            outputs[op] = output;
+            if algorithm.carriers & (1 << op) != 0 {
+                sample += output;
+            }
+
+            let increment = match operator.mode {
+                FrequencyMode::Ratio => frequency * operator.ratio,
+                FrequencyMode::Fixed => operator.frequency,
+            } / sample_rate;
+            self.phases[op] = (self.phases[op] + increment) % 1.0;
+        }
+
+        sample
+            / (algorithm.carriers & ((1 << count) - 1))
+                .count_ones()
+                .max(1) as f32
+    }
+}
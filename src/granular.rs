@@ -0,0 +1,263 @@
+use std::{f32::consts::PI, sync::Arc};
+
+use crate::{
+    oscillator::{key_frequency, Generator},
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+    random::Random,
+    sfz::Sample,
+    wavetable::{self, Wavetable},
+};
+
+/// Grains that can play at once in a voice. Grains are dropped when all are busy.
+const MAX_GRAINS: usize = 64;
+/// Key at which samples play back at their original speed.
+const ROOT_KEY: f32 = 60.0;
+
+choice! {
+    pub enum Source {
+        #[default]
+        Sample => "Sample",
+        Wavetable => "Wavetable",
+    }
+}
+
+choice! {
+    pub enum Window {
+        #[default]
+        Hann => "Hann",
+        Triangle => "Triangle",
+        Trapezoid => "Trapezoid",
+        Gaussian => "Gaussian",
+    }
+}
+
+impl Window {
+    /// Amplitude of the window at `t`, which runs from 0 to 1 over the length of a grain.
+    fn gain(self, t: f32) -> f32 {
+        match self {
+            Window::Hann => 0.5 - 0.5 * (2.0 * PI * t).cos(),
+            Window::Triangle => 1.0 - (2.0 * t - 1.0).abs(),
+            Window::Trapezoid => ((1.0 - (2.0 * t - 1.0).abs()) * 4.0).min(1.0),
+            Window::Gaussian => {
+                let x = (t - 0.5) / 0.15;
+                (-0.5 * x * x).exp()
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GranularParams {
+    pub source: Source,
+    /// Length of each grain, in seconds.
+    pub size: f32,
+    /// Grains started per second.
+    pub density: f32,
+    pub position: f32,
+    /// Offset applied to `position` by host modulation, which is not part of the saved state.
+    pub position_modulation: f32,
+    /// Random offset of the position of each grain, as a fraction of the source.
+    pub spray: f32,
+    /// Random detune of each grain, in cents.
+    pub pitch_random: f32,
+    pub window: Window,
+    pub sample: Option<Arc<Sample>>,
+}
+
+impl Default for GranularParams {
+    fn default() -> Self {
+        Self {
+            source: Source::Sample,
+            size: 0.1,
+            density: 20.0,
+            position: 0.0,
+            position_modulation: 0.0,
+            spray: 0.05,
+            pitch_random: 0.0,
+            window: Window::Hann,
+            sample: None,
+        }
+    }
+}
+
+impl GranularParams {
+    pub fn position(&self) -> f32 {
+        (self.position + self.position_modulation).clamp(0.0, 1.0)
+    }
+}
+
+impl ParamGroup for GranularParams {
+    const COUNT: u32 = 7;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Source", "Granular", Source::NAMES)),
+            1 => Some(ParamSpec::new(
+                "Grain Size",
+                "Granular",
+                0.005,
+                0.5,
+                Unit::Seconds,
+            )),
+            2 => Some(ParamSpec::new(
+                "Density",
+                "Granular",
+                1.0,
+                200.0,
+                Unit::Hertz,
+            )),
+            3 => {
+                Some(ParamSpec::new("Position", "Granular", 0.0, 1.0, Unit::Percent).modulatable())
+            }
+            4 => Some(ParamSpec::new("Spray", "Granular", 0.0, 1.0, Unit::Percent)),
+            5 => Some(ParamSpec::new(
+                "Pitch Random",
+                "Granular",
+                0.0,
+                1200.0,
+                Unit::Cents,
+            )),
+            6 => Some(ParamSpec::choice("Window", "Granular", Window::NAMES)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.source.value()),
+            1 => Some(self.size),
+            2 => Some(self.density),
+            3 => Some(self.position),
+            4 => Some(self.spray),
+            5 => Some(self.pitch_random),
+            6 => Some(self.window.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.source = Source::from_value(value),
+            1 => self.size = value,
+            2 => self.density = value,
+            3 => self.position = value.clamp(0.0, 1.0),
+            4 => self.spray = value,
+            5 => self.pitch_random = value,
+            6 => self.window = Window::from_value(value),
+            _ => {}
+        }
+    }
+
+    fn modulate(&mut self, index: u32, amount: f32) {
+        if index == 3 {
+            self.position_modulation = amount;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Grain {
+    active: bool,
+    /// Read position, in samples of the source sample or as a wavetable frame position.
+    position: f64,
+    /// Phase within the cycle when reading a wavetable.
+    phase: f32,
+    /// Playback speed relative to the note.
+    pitch: f32,
+    age: usize,
+    length: usize,
+}
+
+/// Plays overlapping windowed grains taken from around a position in the source. All grains
+/// live in a fixed pool, so nothing is allocated while playing.
+pub struct GranularVoice {
+    tables: &'static [Wavetable],
+    random: Random,
+    grains: [Grain; MAX_GRAINS],
+    /// Samples left until the next grain starts.
+    countdown: f32,
+}
+
+impl Default for GranularVoice {
+    fn default() -> Self {
+        Self {
+            tables: wavetable::builtin(),
+            random: Random::unique(),
+            grains: [Grain::default(); MAX_GRAINS],
+            countdown: 0.0,
+        }
+    }
+}
+
+impl GranularVoice {
+    fn spawn(&mut self, params: &GranularParams, sample_rate: f32) {
+        let Some(grain) = self.grains.iter_mut().find(|grain| !grain.active) else {
+            return;
+        };
+
+        let position =
+            (params.position() + self.random.next_bipolar() * params.spray).clamp(0.0, 1.0) as f64;
+        *grain = Grain {
+            active: true,
+            position: match (&params.source, &params.sample) {
+                (Source::Sample, Some(sample)) => position * sample.data.len() as f64,
+                _ => position,
+            },
+            phase: self.random.next_f32(),
+            pitch: 2f32.powf(self.random.next_bipolar() * params.pitch_random / 1200.0),
+            age: 0,
+            length: ((params.size * sample_rate) as usize).max(1),
+        };
+    }
+}
+
+impl Generator for GranularVoice {
+    fn start(&mut self, _params: &Parameters, _key: u16, _velocity: f32) {
+        self.grains
+            .iter_mut()
+            .for_each(|grain| grain.active = false);
+        self.countdown = 0.0;
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let granular = &params.granular;
+
+        self.countdown -= 1.0;
+        if self.countdown <= 0.0 {
+            self.spawn(granular, sample_rate);
+            self.countdown += sample_rate / granular.density.max(0.1);
+        }
+
+        let table = params.wavetable.table(self.tables);
+        let mut output = 0.0;
+        for grain in self.grains.iter_mut().filter(|grain| grain.active) {
+            let sample = match (granular.source, &granular.sample, table) {
+                (Source::Sample, Some(sample), _) => {
+                    let value = sample.read(grain.position);
+                    grain.position +=
+                        (frequency / key_frequency(ROOT_KEY) * grain.pitch * sample.sample_rate
+                            / sample_rate) as f64;
+                    value
+                }
+                (Source::Wavetable, _, Some(table)) => {
+                    let frequency = frequency * grain.pitch;
+                    let value =
+                        table.sample(grain.position as f32, grain.phase, frequency, sample_rate);
+                    grain.phase = (grain.phase + frequency / sample_rate) % 1.0;
+                    value
+                }
+                _ => 0.0,
+            };
+
+            output += sample * granular.window.gain(grain.age as f32 / grain.length as f32);
+            grain.age += 1;
+            if grain.age >= grain.length {
+                grain.active = false;
+            }
+        }
+
+        // Keep the level roughly constant as grains start to overlap
+        let overlap = granular.size * granular.density;
+        output / overlap.max(1.0).sqrt()
+    }
+}
@@ -1,14 +1,38 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+};
 
 use baseview::{Size, WindowHandle, WindowOpenOptions, WindowScalePolicy};
 use clack_plugin::plugin::PluginError;
 use egui_baseview::{
-    egui::{self, Context, Slider},
+    egui::{
+        self, pos2, vec2, ComboBox, Context, DragValue, Grid, ScrollArea, Sense, Shape, Slider,
+        Stroke, TextEdit, Ui,
+    },
     EguiWindow, GraphicsConfig, Queue,
 };
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
-use crate::{envelope::Envelope, CrabHowlerShared};
+use crate::{
+    additive::{AdditiveParams, MAX_PARTIALS},
+    effects::{EffectType, EffectsParams, SLOTS},
+    eq::EqParams,
+    harmony::{Chord, HarmonyParams},
+    oscillator::Engine,
+    params::{self, ParamGroup, Parameters, Unit},
+    sequencer::{SequencerParams, Step},
+    sfz::{Instrument, Sample},
+    sysex::Dx7Bank,
+    wavetable::{self, Table, UserWavetable},
+    CrabHowlerShared,
+};
+
+pub const WIDTH: u32 = 480;
+pub const HEIGHT: u32 = 640;
 
 pub struct CrabHowlerGui {
     pub parent: Option<RawWindowHandle>,
@@ -24,6 +48,32 @@ impl Default for CrabHowlerGui {
     }
 }
 
+/// Everything the editor window needs access to, along with state that only lives as long as
+/// the window is open.
+struct GuiState {
+    params: Arc<RwLock<Parameters>>,
+    dx7_bank: Arc<RwLock<Dx7Bank>>,
+    sample_rate: Arc<RwLock<f32>>,
+    bank_path: String,
+    status: String,
+    /// Receives the bank being read in the background, if any.
+    bank_loading: Option<Receiver<Result<Dx7Bank, String>>>,
+    wavetable_path: String,
+    /// Frame size used for WAV files that don't declare their own.
+    frame_size: usize,
+    wavetable_status: String,
+    /// Receives the wavetable being built in the background, if any.
+    wavetable_loading: Option<Receiver<Result<UserWavetable, String>>>,
+    sfz_path: String,
+    sfz_status: String,
+    /// Receives the instrument being loaded in the background, if any.
+    sfz_loading: Option<Receiver<Result<Instrument, String>>>,
+    grain_path: String,
+    grain_status: String,
+    /// Receives the sample being loaded in the background, if any.
+    grain_loading: Option<Receiver<Result<Sample, String>>>,
+}
+
 unsafe impl HasRawWindowHandle for CrabHowlerGui {
     fn raw_window_handle(&self) -> RawWindowHandle {
         self.parent.unwrap()
@@ -38,7 +88,7 @@ impl CrabHowlerGui {
 
         let settings = WindowOpenOptions {
             title: "CrabHowler".to_string(),
-            size: Size::new(400.0, 200.0),
+            size: Size::new(WIDTH as f64, HEIGHT as f64),
             scale: WindowScalePolicy::SystemScaleFactor,
             gl_config: Some(Default::default()),
         };
@@ -47,17 +97,105 @@ impl CrabHowlerGui {
             self,
             settings,
             GraphicsConfig::default(),
-            state.envelope.clone(),
-            |_egui_ctx: &Context, _queue: &mut Queue, _state: &mut Arc<RwLock<Envelope>>| {},
-            |egui_ctx: &Context, _queue: &mut Queue, state: &mut Arc<RwLock<Envelope>>| {
-                let mut envelope = state.write().unwrap();
+            GuiState {
+                params: state.params.clone(),
+                dx7_bank: state.dx7_bank.clone(),
+                sample_rate: state.sample_rate.clone(),
+                bank_path: String::new(),
+                status: String::new(),
+                bank_loading: None,
+                wavetable_path: String::new(),
+                frame_size: wavetable::TABLE_SIZE,
+                wavetable_status: String::new(),
+                wavetable_loading: None,
+                sfz_path: String::new(),
+                sfz_status: String::new(),
+                sfz_loading: None,
+                grain_path: String::new(),
+                grain_status: String::new(),
+                grain_loading: None,
+            },
+            |_egui_ctx: &Context, _queue: &mut Queue, _state: &mut GuiState| {},
+            |egui_ctx: &Context, _queue: &mut Queue, state: &mut GuiState| {
+                let params = state.params.clone();
+                let mut params = params.write().unwrap();
 
                 egui::CentralPanel::default().show(egui_ctx, |ui| {
                     ui.heading("Crab Howler");
-                    ui.add(Slider::new(&mut envelope.attack, 0.0..=1.0).text("Attack"));
-                    ui.add(Slider::new(&mut envelope.decay, 0.0..=1.0).text("Decay"));
-                    ui.add(Slider::new(&mut envelope.sustain, 0.0..=1.0).text("Sustain"));
-                    ui.add(Slider::new(&mut envelope.release, 0.0..=1.0).text("Release"));
+                    ScrollArea::vertical().show(ui, |ui| {
+                        let envelope = &mut params.envelope;
+                        ui.add(Slider::new(&mut envelope.attack, 0.0..=1.0).text("Attack"));
+                        ui.add(Slider::new(&mut envelope.decay, 0.0..=1.0).text("Decay"));
+                        ui.add(Slider::new(&mut envelope.sustain, 0.0..=1.0).text("Sustain"));
+                        ui.add(Slider::new(&mut envelope.release, 0.0..=1.0).text("Release"));
+
+                        ui.separator();
+                        param_group(ui, &mut params.arpeggiator);
+
+                        ui.separator();
+                        param_group(ui, &mut params.sequencer);
+                        step_editor(ui, &mut params.sequencer);
+
+                        ui.separator();
+                        param_group(ui, &mut params.harmony);
+                        if params.harmony.chord == Chord::Custom {
+                            chord_shape(ui, &mut params.harmony);
+                        }
+
+                        ui.separator();
+                        param_group(ui, &mut params.thru);
+
+                        ui.separator();
+                        param_group(ui, &mut params.mixer);
+
+                        ui.separator();
+                        param_group(ui, &mut params.shaper);
+
+                        ui.separator();
+                        param_group(ui, &mut params.filter);
+
+                        ui.separator();
+                        param_group(ui, &mut params.oscillator);
+                        match params.oscillator.engine {
+                            Engine::Analog => param_group(ui, &mut params.analog),
+                            Engine::Fm => param_group(ui, &mut params.fm),
+                            Engine::Dx7 => {
+                                dx7_bank(ui, state, &mut params);
+                                param_group(ui, &mut params.dx7);
+                            }
+                            Engine::Wavetable => {
+                                param_group(ui, &mut params.wavetable);
+                                user_wavetable(ui, state, &mut params);
+                                wavetable_preview(ui, &params);
+                            }
+                            Engine::Pluck => param_group(ui, &mut params.pluck),
+                            Engine::Sampler => {
+                                sfz_instrument(ui, state, &mut params);
+                                param_group(ui, &mut params.sampler);
+                            }
+                            Engine::Granular => {
+                                param_group(ui, &mut params.granular);
+                                granular_sample(ui, state, &mut params);
+                            }
+                            Engine::Additive => {
+                                param_group(ui, &mut params.additive);
+                                spectrum_editor(ui, &mut params.additive);
+                            }
+                        }
+
+                        ui.separator();
+                        // Curves are drawn for 48 kHz until the host has picked a sample rate
+                        let sample_rate = *state.sample_rate.read().unwrap();
+                        let sample_rate = if sample_rate > 0.0 {
+                            sample_rate
+                        } else {
+                            48000.0
+                        };
+                        effects_rack(ui, &mut params, sample_rate);
+
+                        ui.separator();
+                        param_group(ui, &mut params.dynamics);
+                    });
                 });
             },
         ));
@@ -72,3 +210,517 @@ impl CrabHowlerGui {
         }
     }
 }
+
+fn dx7_bank(ui: &mut Ui, state: &mut GuiState, params: &mut Parameters) {
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut state.bank_path).hint_text("Path to .syx bank"));
+        if ui
+            .add_enabled(state.bank_loading.is_none(), egui::Button::new("Load"))
+            .clicked()
+        {
+            let (sender, receiver) = mpsc::channel();
+            let path = state.bank_path.clone();
+            std::thread::spawn(move || {
+                sender.send(
+                    std::fs::read(&path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|sysex| Dx7Bank::parse(sysex).map_err(str::to_string)),
+                )
+            });
+            state.bank_loading = Some(receiver);
+            state.status = "Loading...".to_string();
+        }
+    });
+
+    if let Some(receiver) = &state.bank_loading {
+        match receiver.try_recv() {
+            Ok(Ok(bank)) => {
+                state.status = format!("Loaded {} patches", bank.patches.len());
+                *state.dx7_bank.write().unwrap() = bank;
+                state.bank_loading = None;
+            }
+            Ok(Err(error)) => {
+                state.status = error;
+                state.bank_loading = None;
+            }
+            Err(TryRecvError::Empty) => ui.ctx().request_repaint(),
+            Err(TryRecvError::Disconnected) => {
+                state.status = "Loading failed".to_string();
+                state.bank_loading = None;
+            }
+        }
+    }
+    if !state.status.is_empty() {
+        ui.label(&state.status);
+    }
+
+    let bank = state.dx7_bank.read().unwrap();
+    Grid::new("dx7_bank").show(ui, |ui| {
+        for (index, patch) in bank.patches.iter().enumerate() {
+            if ui.button(&patch.name).clicked() {
+                params.dx7 = patch.params.clone();
+            }
+            if index % 4 == 3 {
+                ui.end_row();
+            }
+        }
+    });
+}
+
+fn sfz_instrument(ui: &mut Ui, state: &mut GuiState, params: &mut Parameters) {
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut state.sfz_path).hint_text("Path to .sfz instrument"));
+        if ui
+            .add_enabled(state.sfz_loading.is_none(), egui::Button::new("Load"))
+            .clicked()
+        {
+            // Large instruments take a while to read, which would otherwise freeze the window
+            let (sender, receiver) = mpsc::channel();
+            let path = state.sfz_path.clone();
+            std::thread::spawn(move || sender.send(Instrument::load(&path)));
+            state.sfz_loading = Some(receiver);
+            state.sfz_status = "Loading...".to_string();
+        }
+    });
+
+    if let Some(receiver) = &state.sfz_loading {
+        match receiver.try_recv() {
+            Ok(Ok(instrument)) => {
+                params.sampler.instrument = Some(Arc::new(instrument));
+                state.sfz_status.clear();
+                state.sfz_loading = None;
+            }
+            Ok(Err(error)) => {
+                state.sfz_status = error;
+                state.sfz_loading = None;
+            }
+            Err(TryRecvError::Empty) => ui.ctx().request_repaint(),
+            Err(TryRecvError::Disconnected) => {
+                state.sfz_status = "Loading failed".to_string();
+                state.sfz_loading = None;
+            }
+        }
+    }
+    if !state.sfz_status.is_empty() {
+        ui.label(&state.sfz_status);
+    }
+
+    if let Some(instrument) = &params.sampler.instrument {
+        ui.label(format!(
+            "{} ({} regions)",
+            instrument.path,
+            instrument.regions.len()
+        ));
+    }
+}
+
+fn granular_sample(ui: &mut Ui, state: &mut GuiState, params: &mut Parameters) {
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut state.grain_path).hint_text("Path to .wav sample"));
+        if ui
+            .add_enabled(state.grain_loading.is_none(), egui::Button::new("Load"))
+            .clicked()
+        {
+            let (sender, receiver) = mpsc::channel();
+            let path = state.grain_path.clone();
+            std::thread::spawn(move || sender.send(Sample::load(Path::new(&path))));
+            state.grain_loading = Some(receiver);
+            state.grain_status = "Loading...".to_string();
+        }
+    });
+
+    if let Some(receiver) = &state.grain_loading {
+        match receiver.try_recv() {
+            Ok(Ok(sample)) => {
+                params.granular.sample = Some(Arc::new(sample));
+                state.grain_status.clear();
+                state.grain_loading = None;
+            }
+            Ok(Err(error)) => {
+                state.grain_status = error;
+                state.grain_loading = None;
+            }
+            Err(TryRecvError::Empty) => ui.ctx().request_repaint(),
+            Err(TryRecvError::Disconnected) => {
+                state.grain_status = "Loading failed".to_string();
+                state.grain_loading = None;
+            }
+        }
+    }
+    if !state.grain_status.is_empty() {
+        ui.label(&state.grain_status);
+    }
+
+    if let Some(sample) = &params.granular.sample {
+        ui.label(format!(
+            "{} ({:.2} s)",
+            sample.path,
+            sample.data.len() as f32 / sample.sample_rate
+        ));
+    }
+    ui.label("The wavetable source uses the table selected in the Wavetable engine.");
+}
+
+fn user_wavetable(ui: &mut Ui, state: &mut GuiState, params: &mut Parameters) {
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut state.wavetable_path).hint_text("Path to .wav table"));
+        ui.add(
+            DragValue::new(&mut state.frame_size)
+                .range(2..=8192)
+                .prefix("Frame size: "),
+        );
+        if ui
+            .add_enabled(state.wavetable_loading.is_none(), egui::Button::new("Load"))
+            .clicked()
+        {
+            // Building the mipmaps of a large table takes a while, and the audio thread would be
+            // kept waiting on the parameters meanwhile
+            let (sender, receiver) = mpsc::channel();
+            let path = state.wavetable_path.clone();
+            let frame_size = state.frame_size;
+            std::thread::spawn(move || {
+                let name = Path::new(&path)
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                sender.send(
+                    std::fs::read(&path)
+                        .map_err(|error| error.to_string())
+                        .and_then(|wav| {
+                            UserWavetable::load(name, wav, frame_size).map_err(str::to_string)
+                        }),
+                )
+            });
+            state.wavetable_loading = Some(receiver);
+            state.wavetable_status = "Loading...".to_string();
+        }
+    });
+
+    if let Some(receiver) = &state.wavetable_loading {
+        match receiver.try_recv() {
+            Ok(Ok(table)) => {
+                params.wavetable.user = Some(Arc::new(table));
+                params.wavetable.table = Table::User;
+                state.wavetable_status.clear();
+                state.wavetable_loading = None;
+            }
+            Ok(Err(error)) => {
+                state.wavetable_status = error;
+                state.wavetable_loading = None;
+            }
+            Err(TryRecvError::Empty) => ui.ctx().request_repaint(),
+            Err(TryRecvError::Disconnected) => {
+                state.wavetable_status = "Loading failed".to_string();
+                state.wavetable_loading = None;
+            }
+        }
+    }
+    if !state.wavetable_status.is_empty() {
+        ui.label(&state.wavetable_status);
+    }
+
+    if let Some(user) = &params.wavetable.user {
+        ui.label(format!(
+            "User table: {} ({} frames of {} samples)",
+            user.name,
+            user.table.frames(),
+            user.frame_size
+        ));
+    }
+}
+
+/// Shows the amplitude of every partial as a bar, which can be drawn by dragging over them.
+/// The bars are drawn as heard, with tilt and odd/even balance applied on top.
+fn spectrum_editor(ui: &mut Ui, params: &mut AdditiveParams) {
+    let (response, painter) =
+        ui.allocate_painter(vec2(ui.available_width(), 128.0), Sense::click_and_drag());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let width = rect.width() / MAX_PARTIALS as f32;
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let index = ((pointer.x - rect.left()) / width) as usize;
+        if let Some(amplitude) = params.spectrum.get_mut(index) {
+            *amplitude = ((rect.bottom() - pointer.y) / rect.height()).clamp(0.0, 1.0);
+        }
+    }
+
+    let visuals = ui.visuals();
+    for index in 0..MAX_PARTIALS {
+        let left = rect.left() + index as f32 * width;
+        let drawn = egui::Rect::from_min_max(
+            pos2(
+                left + 1.0,
+                rect.bottom() - params.spectrum[index] * rect.height(),
+            ),
+            pos2(left + width - 1.0, rect.bottom()),
+        );
+        painter.rect_filled(drawn, 0.0, visuals.widgets.inactive.bg_fill);
+
+        let heard = params.amplitude(index).min(1.0);
+        painter.line_segment(
+            [
+                pos2(left + 1.0, rect.bottom() - heard * rect.height()),
+                pos2(left + width - 1.0, rect.bottom() - heard * rect.height()),
+            ],
+            Stroke::new(2.0, visuals.widgets.active.fg_stroke.color),
+        );
+    }
+}
+
+/// Draws the current frame of the selected table.
+fn wavetable_preview(ui: &mut Ui, params: &Parameters) {
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 96.0), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let Some(table) = params.wavetable.table(wavetable::builtin()) else {
+        return;
+    };
+    let position = params.wavetable.position();
+    let points = (0..=rect.width() as usize)
+        .map(|x| {
+            let phase = x as f32 / rect.width();
+            let sample = table.sample_unfiltered(position, phase);
+            pos2(
+                rect.left() + x as f32,
+                rect.center().y - sample * rect.height() * 0.45,
+            )
+        })
+        .collect();
+    painter.add(Shape::line(
+        points,
+        Stroke::new(1.5, ui.visuals().widgets.active.fg_stroke.color),
+    ));
+}
+
+/// Lists the slots of the effects rack, with buttons to move effects up and down the chain.
+fn effects_rack(ui: &mut Ui, params: &mut Parameters, sample_rate: f32) {
+    ui.label("Effects");
+    let count = EffectsParams::COUNT / SLOTS as u32;
+    for slot in 0..SLOTS {
+        let first = slot as u32 * count;
+        ui.horizontal(|ui| {
+            let effects = &mut params.effects;
+            if ui.add_enabled(slot > 0, egui::Button::new("⏶")).clicked() {
+                effects.slot.swap(slot, slot - 1);
+            }
+            if ui
+                .add_enabled(slot + 1 < SLOTS, egui::Button::new("⏷"))
+                .clicked()
+            {
+                effects.slot.swap(slot, slot + 1);
+            }
+            let effect = effects.slot[slot].effect;
+            param_widget(ui, effects, first);
+            let slot = &mut effects.slot[slot];
+            if slot.effect != effect {
+                slot.mix = slot.effect.default_mix();
+            }
+        });
+        ui.indent(slot, |ui| {
+            (first + 1..first + count)
+                .for_each(|index| param_widget(ui, &mut params.effects, index));
+            match params.effects.slot[slot].effect {
+                EffectType::Empty => {}
+                EffectType::Delay => param_group(ui, &mut params.delay),
+                EffectType::Reverb => param_group(ui, &mut params.reverb),
+                EffectType::Chorus => param_group(ui, &mut params.chorus),
+                EffectType::Flanger => param_group(ui, &mut params.flanger),
+                EffectType::Phaser => param_group(ui, &mut params.phaser),
+                EffectType::Equalizer => {
+                    param_group(ui, &mut params.eq);
+                    eq_response(ui, &params.eq, sample_rate);
+                }
+                EffectType::Bitcrusher => param_group(ui, &mut params.bitcrusher),
+            }
+        });
+    }
+}
+
+/// Lanes for the pitch, velocity, gate and modulation of every step, drawn by dragging across
+/// them, and a row of switches for the ties.
+fn step_editor(ui: &mut Ui, params: &mut SequencerParams) {
+    let count = params.steps();
+    let steps = &mut params.pattern[..count];
+    step_lane(ui, "Pitch", steps, (-24.0, 24.0), |step| &mut step.pitch);
+    step_lane(ui, "Velocity", steps, (0.0, 1.0), |step| &mut step.velocity);
+    step_lane(ui, "Gate", steps, (0.0, 1.0), |step| &mut step.gate);
+    step_lane(ui, "Mod", steps, (-1.0, 1.0), |step| &mut step.modulation);
+
+    toggle_row(ui, "Tie", steps, |step| &mut step.tie, |_| false);
+}
+
+/// A row of switches for the notes of the custom chord, one per semitone above the played key.
+fn chord_shape(ui: &mut Ui, params: &mut HarmonyParams) {
+    // Octaves of the played key stand out, like the C keys of a keyboard
+    toggle_row(
+        ui,
+        "Chord Shape",
+        &mut params.shape,
+        |on| on,
+        |interval| interval % 12 == 0,
+    );
+}
+
+/// A row of switches, one per item, flipped by clicking them. Off switches for which `marked`
+/// holds are drawn a little lighter.
+fn toggle_row<T>(
+    ui: &mut Ui,
+    label: &str,
+    items: &mut [T],
+    value: fn(&mut T) -> &mut bool,
+    marked: fn(usize) -> bool,
+) {
+    ui.label(label);
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 16.0), Sense::click());
+    let rect = response.rect;
+    let width = rect.width() / items.len() as f32;
+    if let Some(pointer) = response
+        .interact_pointer_pos()
+        .filter(|_| response.clicked())
+    {
+        if let Some(item) = items.get_mut(((pointer.x - rect.left()) / width) as usize) {
+            let on = value(item);
+            *on = !*on;
+        }
+    }
+    let visuals = ui.visuals();
+    for (index, item) in items.iter_mut().enumerate() {
+        let left = rect.left() + index as f32 * width;
+        let cell = egui::Rect::from_min_max(
+            pos2(left + 1.0, rect.top()),
+            pos2(left + width - 1.0, rect.bottom()),
+        );
+        let fill = if *value(item) {
+            visuals.widgets.active.fg_stroke.color
+        } else if marked(index) {
+            visuals.widgets.inactive.bg_fill
+        } else {
+            visuals.extreme_bg_color
+        };
+        painter.rect_filled(cell, 2.0, fill);
+    }
+}
+
+fn step_lane(
+    ui: &mut Ui,
+    label: &str,
+    steps: &mut [Step],
+    (min, max): (f32, f32),
+    value: fn(&mut Step) -> &mut f32,
+) {
+    ui.label(label);
+    let (response, painter) =
+        ui.allocate_painter(vec2(ui.available_width(), 48.0), Sense::click_and_drag());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+
+    let width = rect.width() / steps.len() as f32;
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let index = ((pointer.x - rect.left()) / width) as usize;
+        if let Some(step) = steps.get_mut(index) {
+            let t = ((rect.bottom() - pointer.y) / rect.height()).clamp(0.0, 1.0);
+            // Pitches snap to semitones
+            let scale = if max > 1.0 { 1.0 } else { 100.0 };
+            *value(step) = ((min + t * (max - min)) * scale).round() / scale;
+        }
+    }
+
+    // Bars grow from zero, which is in the middle of the bipolar lanes
+    let y = |v: f32| rect.bottom() - (v - min) / (max - min) * rect.height();
+    let visuals = ui.visuals();
+    for (index, step) in steps.iter_mut().enumerate() {
+        let left = rect.left() + index as f32 * width;
+        let bar = egui::Rect::from_two_pos(
+            pos2(left + 1.0, y(0.0f32.clamp(min, max))),
+            pos2(left + width - 1.0, y(*value(step))),
+        );
+        painter.rect_filled(bar, 0.0, visuals.widgets.inactive.bg_fill);
+    }
+}
+
+/// Plots the gain of the EQ from 20 Hz to 20 kHz, between -24 and +24 dB.
+fn eq_response(ui: &mut Ui, params: &EqParams, sample_rate: f32) {
+    let (response, painter) = ui.allocate_painter(vec2(ui.available_width(), 96.0), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    painter.hline(
+        rect.x_range(),
+        rect.center().y,
+        Stroke::new(1.0, ui.visuals().widgets.noninteractive.bg_stroke.color),
+    );
+
+    let stages = params.coefficients(sample_rate);
+    let points = (0..=rect.width() as usize)
+        .map(|x| {
+            let frequency = 20.0 * 1000f32.powf(x as f32 / rect.width());
+            let gain: f32 = stages
+                .iter()
+                .map(|stage| stage.magnitude(frequency, sample_rate))
+                .product();
+            let db = (20.0 * gain.max(1e-6).log10()).clamp(-24.0, 24.0);
+            pos2(
+                rect.left() + x as f32,
+                rect.center().y - db / 24.0 * rect.height() * 0.5,
+            )
+        })
+        .collect();
+    painter.add(Shape::line(
+        points,
+        Stroke::new(1.5, ui.visuals().widgets.active.fg_stroke.color),
+    ));
+}
+
+/// Shows a widget for every parameter in `group`, with nested modules in collapsible sections.
+fn param_group<G: ParamGroup>(ui: &mut Ui, group: &mut G) {
+    let mut index = 0;
+    while index < G::COUNT {
+        let Some(module) = G::spec(index).map(|spec| spec.module) else {
+            break;
+        };
+        let end = (index..G::COUNT)
+            .find(|i| G::spec(*i).is_none_or(|spec| spec.module != module))
+            .unwrap_or(G::COUNT);
+
+        if module.contains('/') {
+            let title = module.rsplit('/').next().unwrap_or_default().to_string();
+            ui.collapsing(title, |ui| {
+                (index..end).for_each(|index| param_widget(ui, group, index))
+            });
+        } else {
+            (index..end).for_each(|index| param_widget(ui, group, index));
+        }
+        index = end;
+    }
+}
+
+fn param_widget<G: ParamGroup>(ui: &mut Ui, group: &mut G, index: u32) {
+    let (Some(spec), Some(mut value)) = (G::spec(index), group.get(index)) else {
+        return;
+    };
+
+    let changed = match spec.unit {
+        Unit::Choice(names) => {
+            let mut selected = value.round() as usize;
+            let response =
+                ComboBox::from_label(&spec.name)
+                    .show_index(ui, &mut selected, names.len(), |i| names[i]);
+            value = selected as f32;
+            response.changed()
+        }
+        unit => ui
+            .add(
+                Slider::new(&mut value, spec.min..=spec.max)
+                    .text(&spec.name)
+                    .logarithmic(matches!(unit, Unit::Hertz | Unit::Milliseconds))
+                    .step_by(if unit.is_stepped() { 1.0 } else { 0.0 })
+                    .custom_formatter(|value, _| params::display(unit, value as f32)),
+            )
+            .changed(),
+    };
+
+    if changed {
+        group.set(index, value);
+    }
+}
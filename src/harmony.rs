@@ -0,0 +1,280 @@
+use clack_plugin::events::{
+    event_types::{NoteOffEvent, NoteOnEvent},
+    Match, Pckn,
+};
+
+use crate::{
+    notes,
+    params::{choice, ParamGroup, ParamSpec},
+};
+
+/// Widest custom chord shape, in semitones above the played key.
+pub const MAX_INTERVAL: usize = 24;
+/// Most notes a single key can turn into.
+const MAX_CHORD: usize = MAX_INTERVAL + 1;
+/// Most keys followed at once.
+const MAX_KEYS: usize = 128;
+
+choice! {
+    pub enum Chord {
+        #[default]
+        Off => "Off",
+        Major => "Major",
+        Minor => "Minor",
+        Sus2 => "Sus2",
+        Sus4 => "Sus4",
+        Major7 => "Major 7th",
+        Minor7 => "Minor 7th",
+        Dominant7 => "Dominant 7th",
+        Power => "Power",
+        Octaves => "Octaves",
+        Custom => "Custom",
+    }
+}
+
+choice! {
+    pub enum Scale {
+        #[default]
+        Off => "Off",
+        Major => "Major",
+        Minor => "Minor",
+        HarmonicMinor => "Harmonic Minor",
+        Dorian => "Dorian",
+        Mixolydian => "Mixolydian",
+        MajorPentatonic => "Major Pentatonic",
+        MinorPentatonic => "Minor Pentatonic",
+        Blues => "Blues",
+    }
+}
+
+choice! {
+    pub enum Root {
+        #[default]
+        C => "C",
+        CSharp => "C#",
+        D => "D",
+        DSharp => "D#",
+        E => "E",
+        F => "F",
+        FSharp => "F#",
+        G => "G",
+        GSharp => "G#",
+        A => "A",
+        ASharp => "A#",
+        B => "B",
+    }
+}
+
+impl Chord {
+    /// Semitones above the played key of every note in the chord.
+    fn intervals(self) -> &'static [u16] {
+        match self {
+            Chord::Off | Chord::Custom => &[0],
+            Chord::Major => &[0, 4, 7],
+            Chord::Minor => &[0, 3, 7],
+            Chord::Sus2 => &[0, 2, 7],
+            Chord::Sus4 => &[0, 5, 7],
+            Chord::Major7 => &[0, 4, 7, 11],
+            Chord::Minor7 => &[0, 3, 7, 10],
+            Chord::Dominant7 => &[0, 4, 7, 10],
+            Chord::Power => &[0, 7, 12],
+            Chord::Octaves => &[0, 12],
+        }
+    }
+}
+
+impl Scale {
+    /// Semitones above the root that belong to the scale.
+    fn degrees(self) -> &'static [u16] {
+        match self {
+            Scale::Off => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HarmonyParams {
+    pub chord: Chord,
+    pub scale: Scale,
+    pub root: Root,
+    /// Notes of the custom chord, by semitones above the played key.
+    pub shape: [bool; MAX_INTERVAL + 1],
+}
+
+impl Default for HarmonyParams {
+    fn default() -> Self {
+        let mut shape = [false; MAX_INTERVAL + 1];
+        for interval in Chord::Major.intervals() {
+            shape[*interval as usize] = true;
+        }
+        Self {
+            chord: Chord::Off,
+            scale: Scale::Off,
+            root: Root::C,
+            shape,
+        }
+    }
+}
+
+impl HarmonyParams {
+    /// Moves a key to the nearest one in the scale, going down when two are equally near.
+    fn quantise(&self, key: u16) -> u16 {
+        let degrees = self.scale.degrees();
+        let in_scale =
+            |key: i32| degrees.contains(&((key - self.root as i32).rem_euclid(12) as u16));
+        let key = key as i32;
+        (0..12)
+            .flat_map(|distance| [key - distance, key + distance])
+            .find(|key| in_scale(*key))
+            .unwrap_or(key)
+            .clamp(0, 127) as u16
+    }
+
+    /// Serializes the custom chord shape for the plugin state.
+    pub fn shape_bytes(&self) -> Vec<u8> {
+        self.shape.iter().map(|on| *on as u8).collect()
+    }
+
+    pub fn load_shape(&mut self, data: &[u8]) {
+        for (on, byte) in self.shape.iter_mut().zip(data) {
+            *on = *byte != 0;
+        }
+    }
+}
+
+impl ParamGroup for HarmonyParams {
+    const COUNT: u32 = 3;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Chord", "Harmony", Chord::NAMES)),
+            1 => Some(ParamSpec::choice("Scale", "Harmony", Scale::NAMES)),
+            2 => Some(ParamSpec::choice("Scale Root", "Harmony", Root::NAMES)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.chord.value()),
+            1 => Some(self.scale.value()),
+            2 => Some(self.root.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.chord = Chord::from_value(value),
+            1 => self.scale = Scale::from_value(value),
+            2 => self.root = Root::from_value(value),
+            _ => {}
+        }
+    }
+}
+
+/// A key that is being played, and the notes it was turned into.
+#[derive(Clone, Copy)]
+struct Held {
+    pckn: Pckn,
+    keys: [u16; MAX_CHORD],
+    count: usize,
+}
+
+/// Turns every played key into a chord, with all notes pulled into a scale, right before the
+/// notes reach the oscillators. The notes each key became are remembered, so that they are
+/// released together even if the settings change in the meantime.
+pub struct Harmony {
+    held: Vec<Held>,
+}
+
+impl Default for Harmony {
+    fn default() -> Self {
+        Self {
+            held: Vec::with_capacity(MAX_KEYS),
+        }
+    }
+}
+
+impl Harmony {
+    pub fn note_on(
+        &mut self,
+        params: &HarmonyParams,
+        event: &NoteOnEvent,
+        mut play: impl FnMut(&NoteOnEvent),
+    ) {
+        let Match::Specific(key) = event.key() else {
+            return play(event);
+        };
+        if (params.chord, params.scale) == (Chord::Off, Scale::Off) || self.held.len() >= MAX_KEYS {
+            return play(event);
+        }
+
+        let mut held = Held {
+            pckn: event.pckn(),
+            keys: [0; MAX_CHORD],
+            count: 0,
+        };
+        let intervals = (0..=MAX_INTERVAL as u16).filter(|interval| match params.chord {
+            // A cleared shape still plays the key itself, rather than silencing it
+            Chord::Custom if !params.shape.contains(&true) => *interval == 0,
+            Chord::Custom => params.shape[*interval as usize],
+            chord => chord.intervals().contains(interval),
+        });
+        for interval in intervals {
+            let note = params.quantise((key + interval).min(127));
+            // Quantising can fold neighbouring notes of a chord onto each other
+            if held.keys[..held.count].contains(&note) {
+                continue;
+            }
+            held.keys[held.count] = note;
+            held.count += 1;
+            let pckn = Pckn {
+                key: Match::Specific(note),
+                ..held.pckn
+            };
+            play(&NoteOnEvent::new(
+                event.header().time(),
+                pckn,
+                event.velocity(),
+            ));
+        }
+        self.held.push(held);
+    }
+
+    /// Releases the chords of every held key `event` ends, which can be several when it leaves
+    /// the channel or key open.
+    pub fn note_off(&mut self, event: &NoteOffEvent, mut play: impl FnMut(&NoteOffEvent)) {
+        let mut found = false;
+        self.held.retain(|held| {
+            if !notes::applies_to(&event.pckn(), &held.pckn) {
+                return true;
+            }
+            found = true;
+            for note in &held.keys[..held.count] {
+                let pckn = Pckn {
+                    key: Match::Specific(*note),
+                    ..held.pckn
+                };
+                play(&NoteOffEvent::new(
+                    event.header().time(),
+                    pckn,
+                    event.velocity(),
+                ));
+            }
+            false
+        });
+        // A wildcard also ends the keys that were played as they came in
+        if !found || event.channel() == Match::All || event.key() == Match::All {
+            play(event);
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use arpeggiator::Arpeggiator;
 use clack_extensions::{
     audio_ports::{
         AudioPortFlags, AudioPortInfo, AudioPortInfoWriter, AudioPortType, PluginAudioPorts,
         PluginAudioPortsImpl,
     },
     gui::{GuiApiType, GuiConfiguration, PluginGui, PluginGuiImpl},
+    latency::{PluginLatency, PluginLatencyImpl},
     note_ports::{
         NoteDialect, NoteDialects, NotePortInfo, NotePortInfoWriter, PluginNotePorts,
         PluginNotePortsImpl,
@@ -13,11 +15,15 @@ use clack_extensions::{
         PluginMainThreadParams, PluginParams,
     },
     state::{PluginState, PluginStateImpl},
+    tail::{PluginTail, PluginTailImpl, TailLength},
 };
 use clack_plugin::{
     clack_export_entry,
     entry::{DefaultPluginFactory, SinglePluginEntry},
-    events::spaces::CoreEventSpace,
+    events::{
+        event_types::{NoteOffEvent, NoteOnEvent, TransportFlags},
+        spaces::CoreEventSpace,
+    },
     host::{HostAudioProcessorHandle, HostMainThreadHandle, HostSharedHandle},
     plugin::{
         Plugin, PluginAudioProcessor, PluginDescriptor, PluginError, PluginMainThread, PluginShared,
@@ -27,20 +33,59 @@ use clack_plugin::{
     stream::{InputStream, OutputStream},
     utils::ClapId,
 };
-use envelope::Envelope;
+use dynamics::Dynamics;
+use effects::Rack;
 use gui::CrabHowlerGui;
-use oscillator::{Oscillator, SineOscillator};
+use harmony::Harmony;
+use notes::NoteEvent;
+use oscillator::{Engine, Oscillator};
+use params::{Parameters, Toggle};
 use raw_window_handle::HasRawWindowHandle;
+use sequencer::{Sequencer, SequencerMode};
+use sfz::{Instrument, Sample};
 use std::{
     ffi::CStr,
-    io::{Read, Write},
+    path::Path,
     sync::{Arc, RwLock},
 };
+use sysex::Dx7Bank;
+use thru::{Source, Thru};
+use wavetable::UserWavetable;
 
+mod additive;
 mod adsr;
+mod analog;
+mod arpeggiator;
+mod bitcrusher;
+mod delay;
+mod dx7;
+mod dynamics;
+mod effects;
 mod envelope;
+mod eq;
+mod filter;
+mod fm;
+mod granular;
 mod gui;
+mod harmony;
+mod mixer;
+mod modulation;
+mod notes;
 mod oscillator;
+mod oversampling;
+mod params;
+mod pluck;
+mod random;
+mod reverb;
+mod sampler;
+mod sequencer;
+mod sfz;
+mod shaper;
+mod state;
+mod sysex;
+mod thru;
+mod wav;
+mod wavetable;
 
 pub struct CrabHowler;
 
@@ -55,7 +100,9 @@ impl Plugin for CrabHowler {
             .register::<PluginNotePorts>()
             .register::<PluginParams>()
             .register::<PluginState>()
-            .register::<PluginGui>();
+            .register::<PluginGui>()
+            .register::<PluginTail>()
+            .register::<PluginLatency>();
     }
 }
 
@@ -79,12 +126,22 @@ impl DefaultPluginFactory for CrabHowler {
         Ok(Self::MainThread {
             shared,
             gui: CrabHowlerGui::default(),
+            latency: 0,
         })
     }
 }
 
 pub struct CrabHowlerAudioProcessor<'a> {
-    osc: Box<dyn Oscillator + Send>,
+    /// One oscillator per engine, so notes started before an engine change can ring out
+    oscillators: Vec<Box<dyn Oscillator + Send>>,
+    arpeggiator: Arpeggiator,
+    sequencer: Sequencer,
+    harmony: Harmony,
+    thru: Thru,
+    rack: Rack,
+    dynamics: Dynamics,
+    /// Tempo of the last processed block, for the tail length of synced effects.
+    tempo: f64,
     shared: &'a CrabHowlerShared,
 }
 
@@ -97,8 +154,25 @@ impl<'a> PluginAudioProcessor<'a, CrabHowlerShared, CrabHowlerMainThread<'a>>
         shared: &'a CrabHowlerShared,
         audio_config: PluginAudioConfiguration,
     ) -> Result<Self, PluginError> {
+        main_thread.latency = Dynamics::latency(audio_config.sample_rate as f32);
+        *shared.sample_rate.write().or(Err(PluginError::Message(
+            "Failed to acquire sample rate write lock",
+        )))? = audio_config.sample_rate as f32;
         Ok(Self {
-            osc: Box::new(SineOscillator::new(audio_config.sample_rate as f32)),
+            oscillators: Engine::ALL
+                .iter()
+                .map(|engine| engine.oscillator(audio_config.sample_rate as f32))
+                .collect(),
+            arpeggiator: Arpeggiator::new(audio_config.sample_rate as f32),
+            sequencer: Sequencer::new(audio_config.sample_rate as f32),
+            harmony: Harmony::default(),
+            thru: Thru::default(),
+            rack: Rack::new(
+                audio_config.sample_rate as f32,
+                audio_config.max_frames_count as usize,
+            ),
+            dynamics: Dynamics::new(audio_config.sample_rate as f32),
+            tempo: 120.0,
             shared,
         })
     }
@@ -131,22 +205,71 @@ impl<'a> PluginAudioProcessor<'a, CrabHowlerShared, CrabHowlerMainThread<'a>>
                 .ok_or(PluginError::Message("Right channel not found"))?,
         );
 
+        // Synced effects fall back to 120 BPM in hosts that don't share their tempo
+        let tempo = process
+            .transport
+            .filter(|transport| transport.flags.contains(TransportFlags::HAS_TEMPO))
+            .map_or(120.0, |transport| transport.tempo);
+        self.tempo = tempo;
+
+        let output = events.output;
+
+        // The arpeggiator and sequencer follow the beat position of the host while it plays
+        let position = process
+            .transport
+            .filter(|transport| {
+                transport.flags.contains(TransportFlags::HAS_BEATS_TIMELINE)
+                    && transport.flags.contains(TransportFlags::IS_PLAYING)
+            })
+            .map(|transport| transport.song_pos_beats.to_float());
+        {
+            let params = self.shared.params.read().or(Err(PluginError::Message(
+                "Failed to acquire parameter read lock",
+            )))?;
+            self.arpeggiator.sync(&params.arpeggiator, position);
+            self.sequencer.sync(&params.sequencer, position);
+        }
+
         for batch in events.input.batch() {
             for event in batch.events() {
                 match event.as_core_event() {
                     Some(CoreEventSpace::NoteOn(event)) => {
-                        let envelope = self.shared.envelope.read().or(Err(
-                            PluginError::Message("Failed to acquire parameter read lock"),
-                        ))?;
-                        self.osc.handle_note_on(&envelope, event)
+                        let params = self.shared.params.read().or(Err(PluginError::Message(
+                            "Failed to acquire parameter read lock",
+                        )))?;
+                        self.thru
+                            .note_on(&params.thru, Source::Incoming, event, output);
+                        if params.sequencer.mode == SequencerMode::Transpose {
+                            self.sequencer.note_on(event)
+                        } else if params.arpeggiator.enabled == Toggle::On {
+                            self.arpeggiator.note_on(&params.arpeggiator, event)
+                        } else {
+                            self.note_on(&params, event, output)
+                        }
+                    }
+                    Some(CoreEventSpace::NoteOff(event)) => {
+                        let params = self.shared.params.read().or(Err(PluginError::Message(
+                            "Failed to acquire parameter read lock",
+                        )))?;
+                        self.thru.note_off(Source::Incoming, event, output);
+                        if !self.sequencer.note_off(event)
+                            && !self.arpeggiator.note_off(&params.arpeggiator, event)
+                        {
+                            self.note_off(event, output);
+                        }
                     }
-                    Some(CoreEventSpace::NoteOff(event)) => self.osc.handle_note_off(event),
                     Some(CoreEventSpace::ParamValue(event)) => self
                         .shared
-                        .envelope
+                        .params
                         .write()
                         .expect("Failed to acquire parameter write lock")
                         .handle_event(event),
+                    Some(CoreEventSpace::ParamMod(event)) => self
+                        .shared
+                        .params
+                        .write()
+                        .expect("Failed to acquire parameter write lock")
+                        .handle_mod_event(event),
                     _ => {}
                 }
             }
@@ -156,17 +279,109 @@ impl<'a> PluginAudioProcessor<'a, CrabHowlerShared, CrabHowlerMainThread<'a>>
                 &mut right[batch.sample_bounds()],
             );
 
-            self.osc.process(left, right);
+            left.fill(0.0);
+            right.fill(0.0);
+
+            // Render up to each generated note, so that it lands on the exact sample
+            let mut start = 0;
+            loop {
+                let params = self.shared.params.read().or(Err(PluginError::Message(
+                    "Failed to acquire parameter read lock",
+                )))?;
+                let until = self
+                    .arpeggiator
+                    .pending(&params.arpeggiator, tempo)
+                    .min(self.sequencer.pending(&params.sequencer, tempo));
+                let end = start + (until as usize).min(left.len() - start);
+                let lane = self.sequencer.modulation(&params.sequencer);
+                for osc in self.oscillators.iter_mut().filter(|osc| osc.is_active()) {
+                    osc.process(&params, lane, &mut left[start..end], &mut right[start..end]);
+                }
+                self.arpeggiator.advance(tempo, end - start);
+                self.sequencer.advance(tempo, end - start);
+                // Notes due right at the end of the batch belong to the next one
+                if until >= (left.len() - start) as f64 {
+                    break;
+                }
+                start = end;
+
+                // Events sent to the host have to fall within the block
+                let time = ((batch.first_sample() + start) as u32)
+                    .min(process.frames_count.saturating_sub(1));
+                if let Some(note) = self.arpeggiator.trigger(&params.arpeggiator, tempo) {
+                    self.play(&params, note.at(time), output);
+                }
+                if let Some(note) = self.sequencer.trigger(&params.sequencer, tempo) {
+                    self.play(&params, note.at(time), output);
+                }
+            }
+
+            let params = self.shared.params.read().or(Err(PluginError::Message(
+                "Failed to acquire parameter read lock",
+            )))?;
+            self.rack.process(&params, tempo, left, right);
+            self.dynamics.process(&params.dynamics, left, right);
         }
 
-        if self.osc.is_active() {
+        let params = self.shared.params.read().or(Err(PluginError::Message(
+            "Failed to acquire parameter read lock",
+        )))?;
+        if self.arpeggiator.is_active()
+            || self.sequencer.is_active()
+            || self.oscillators.iter().any(|osc| osc.is_active())
+        {
             Ok(ProcessStatus::Continue)
+        } else if params.effects.is_active() || self.dynamics.is_active() {
+            Ok(ProcessStatus::Tail)
         } else {
             Ok(ProcessStatus::Sleep)
         }
     }
 }
 
+impl<'a> CrabHowlerAudioProcessor<'a> {
+    /// Passes a note generated by the arpeggiator or sequencer on to the oscillators.
+    fn play(&mut self, params: &Parameters, note: NoteEvent, output: &mut OutputEvents) {
+        match note {
+            NoteEvent::On(event) => self.note_on(params, &event, output),
+            NoteEvent::Off(event) => self.note_off(&event, output),
+        }
+    }
+
+    /// Starts the notes a key turns into on the current engine, and sends them out when the
+    /// note output follows what is played.
+    fn note_on(&mut self, params: &Parameters, event: &NoteOnEvent, output: &mut OutputEvents) {
+        let oscillator = &mut self.oscillators[params.oscillator.engine as usize];
+        let thru = &mut self.thru;
+        self.harmony.note_on(&params.harmony, event, |event| {
+            oscillator.handle_note_on(params, event);
+            thru.note_on(&params.thru, Source::Played, event, output);
+        });
+    }
+
+    fn note_off(&mut self, event: &NoteOffEvent, output: &mut OutputEvents) {
+        let oscillators = &mut self.oscillators;
+        let thru = &mut self.thru;
+        self.harmony.note_off(event, |event| {
+            oscillators
+                .iter_mut()
+                .for_each(|osc| osc.handle_note_off(event));
+            thru.note_off(Source::Played, event, output);
+        });
+    }
+}
+
+impl<'a> PluginTailImpl for CrabHowlerAudioProcessor<'a> {
+    fn get(&self) -> TailLength {
+        match self.shared.params.read() {
+            Ok(params) => {
+                TailLength::Finite(self.rack.tail(&params, self.tempo) + self.dynamics.tail())
+            }
+            Err(_) => TailLength::Infinite,
+        }
+    }
+}
+
 impl<'a> PluginAudioProcessorParams for CrabHowlerAudioProcessor<'a> {
     fn flush(
         &mut self,
@@ -176,7 +391,7 @@ impl<'a> PluginAudioProcessorParams for CrabHowlerAudioProcessor<'a> {
         for event in input_parameter_changes {
             if let Some(CoreEventSpace::ParamValue(event)) = event.as_core_event() {
                 self.shared
-                    .envelope
+                    .params
                     .write()
                     .expect("Failed to acquire parameter write lock")
                     .handle_event(event);
@@ -187,7 +402,10 @@ impl<'a> PluginAudioProcessorParams for CrabHowlerAudioProcessor<'a> {
 
 #[derive(Default)]
 pub struct CrabHowlerShared {
-    envelope: Arc<RwLock<Envelope>>,
+    params: Arc<RwLock<Parameters>>,
+    dx7_bank: Arc<RwLock<Dx7Bank>>,
+    /// Sample rate the plugin was last activated with, or zero before the first activation.
+    sample_rate: Arc<RwLock<f32>>,
 }
 
 impl<'a> PluginShared<'a> for CrabHowlerShared {}
@@ -195,43 +413,40 @@ impl<'a> PluginShared<'a> for CrabHowlerShared {}
 pub struct CrabHowlerMainThread<'a> {
     shared: &'a CrabHowlerShared,
     gui: CrabHowlerGui,
+    /// Latency of the output stage at the sample rate the plugin was last activated with.
+    latency: u32,
 }
 
 impl<'a> PluginMainThreadParams for CrabHowlerMainThread<'a> {
     fn count(&mut self) -> u32 {
-        4
+        Parameters::count()
     }
 
     fn get_info(&mut self, param_index: u32, info: &mut ParamInfoWriter) {
-        if let Some((name, default)) = match param_index {
-            0 => Some(("Attack", Envelope::default().attack)),
-            1 => Some(("Decay", Envelope::default().decay)),
-            2 => Some(("Sustain", Envelope::default().sustain)),
-            3 => Some(("Release", Envelope::default().release)),
-            _ => None,
-        } {
+        if let Some((id, spec, default)) = Parameters::info(param_index) {
+            let mut flags = ParamInfoFlags::IS_AUTOMATABLE;
+            if spec.unit.is_stepped() {
+                flags |= ParamInfoFlags::IS_STEPPED;
+            }
+            if spec.modulatable {
+                flags |= ParamInfoFlags::IS_MODULATABLE;
+            }
             info.set(&ParamInfo {
-                id: param_index.into(),
-                flags: ParamInfoFlags::IS_AUTOMATABLE,
+                id: id.into(),
+                flags,
                 cookie: Default::default(),
-                name: name.as_bytes(),
-                module: b"",
-                min_value: 0.0,
-                max_value: 1.0,
+                name: spec.name.as_bytes(),
+                module: spec.module.as_bytes(),
+                min_value: spec.min as f64,
+                max_value: spec.max as f64,
                 default_value: default as f64,
             });
         }
     }
 
     fn get_value(&mut self, param_id: ClapId) -> Option<f64> {
-        let envelope = self.shared.envelope.read().ok()?;
-        match param_id.into() {
-            0 => Some(envelope.attack as f64),
-            1 => Some(envelope.decay as f64),
-            2 => Some(envelope.sustain as f64),
-            3 => Some(envelope.release as f64),
-            _ => None,
-        }
+        let params = self.shared.params.read().ok()?;
+        params.get(param_id.into()).map(f64::from)
     }
 
     fn value_to_text(
@@ -240,21 +455,11 @@ impl<'a> PluginMainThreadParams for CrabHowlerMainThread<'a> {
         value: f64,
         writer: &mut ParamDisplayWriter,
     ) -> std::fmt::Result {
-        use std::fmt::Write;
-        match param_id.into() {
-            0 | 1 | 3 => write!(writer, "{:.2} s", value),
-            2 => write!(writer, "{:.2} %", value * 100f64),
-            _ => Err(std::fmt::Error),
-        }
+        Parameters::value_to_text(param_id.into(), value, writer)
     }
 
     fn text_to_value(&mut self, param_id: ClapId, text: &CStr) -> Option<f64> {
-        let scale = if param_id == 2 { 0.01 } else { 1.0 };
-        let input = text.to_str().ok()?;
-        let suffix_idx = input
-            .find(|c: char| !c.is_numeric() && c != '.' && c != ',')
-            .unwrap_or_else(|| input.len());
-        input[..suffix_idx].parse().map(|v: f64| v * scale).ok()
+        Parameters::text_to_value(param_id.into(), text)
     }
 
     fn flush(
@@ -265,7 +470,7 @@ impl<'a> PluginMainThreadParams for CrabHowlerMainThread<'a> {
         for event in input_parameter_changes {
             if let Some(CoreEventSpace::ParamValue(event)) = event.as_core_event() {
                 self.shared
-                    .envelope
+                    .params
                     .write()
                     .expect("Failed to acquire parameter write lock")
                     .handle_event(event);
@@ -274,35 +479,116 @@ impl<'a> PluginMainThreadParams for CrabHowlerMainThread<'a> {
     }
 }
 
+const DX7_BANK_CHUNK: &[u8; 4] = b"DX7B";
+const USER_WAVETABLE_CHUNK: &[u8; 4] = b"WAVT";
+const SFZ_PATH_CHUNK: &[u8; 4] = b"SFZP";
+const GRANULAR_SAMPLE_CHUNK: &[u8; 4] = b"GRNS";
+const ADDITIVE_SPECTRUM_CHUNK: &[u8; 4] = b"ADDS";
+const SEQUENCER_PATTERN_CHUNK: &[u8; 4] = b"SEQP";
+const HARMONY_SHAPE_CHUNK: &[u8; 4] = b"CHRD";
+
 impl<'a> PluginStateImpl for CrabHowlerMainThread<'a> {
     fn save(&mut self, output: &mut OutputStream) -> Result<(), PluginError> {
-        let envelope = self.shared.envelope.read().or(Err(PluginError::Message(
+        let params = self.shared.params.read().or(Err(PluginError::Message(
             "Failed to acquire parameter read lock",
         )))?;
-        output.write_all(&envelope.attack.to_le_bytes())?;
-        output.write_all(&envelope.decay.to_le_bytes())?;
-        output.write_all(&envelope.sustain.to_le_bytes())?;
-        output.write_all(&envelope.release.to_le_bytes())?;
+        params.save(output)?;
+        if let Some(user) = &params.wavetable.user {
+            state::write_chunk(output, USER_WAVETABLE_CHUNK, &user.to_bytes())?;
+        }
+        if let Some(instrument) = &params.sampler.instrument {
+            state::write_chunk(output, SFZ_PATH_CHUNK, instrument.path.as_bytes())?;
+        }
+        if let Some(sample) = &params.granular.sample {
+            state::write_chunk(output, GRANULAR_SAMPLE_CHUNK, sample.path.as_bytes())?;
+        }
+        state::write_chunk(
+            output,
+            ADDITIVE_SPECTRUM_CHUNK,
+            &params.additive.spectrum_bytes(),
+        )?;
+        state::write_chunk(
+            output,
+            SEQUENCER_PATTERN_CHUNK,
+            &params.sequencer.pattern_bytes(),
+        )?;
+        state::write_chunk(output, HARMONY_SHAPE_CHUNK, &params.harmony.shape_bytes())?;
+
+        let dx7_bank = self.shared.dx7_bank.read().or(Err(PluginError::Message(
+            "Failed to acquire DX7 bank read lock",
+        )))?;
+        if !dx7_bank.sysex.is_empty() {
+            state::write_chunk(output, DX7_BANK_CHUNK, &dx7_bank.sysex)?;
+        }
         Ok(())
     }
 
     fn load(&mut self, input: &mut InputStream) -> Result<(), PluginError> {
-        let mut envelope = self.shared.envelope.write().or(Err(PluginError::Message(
+        // Loading works on a copy, so that the audio thread isn't kept waiting on the
+        // parameter lock while samples are read from disk
+        let mut params = self
+            .shared
+            .params
+            .read()
+            .or(Err(PluginError::Message(
+                "Failed to acquire parameter read lock",
+            )))?
+            .clone();
+        params.load(input)?;
+        params.wavetable.user = None;
+        params.sampler.instrument = None;
+        params.granular.sample = None;
+        // Data kept in chunks of its own goes back to its default when the chunk is missing
+        let defaults = Parameters::default();
+        params.additive.spectrum = defaults.additive.spectrum;
+        params.sequencer.pattern = defaults.sequencer.pattern;
+        params.harmony.shape = defaults.harmony.shape;
+        // A state without a bank of its own starts from an empty one, rather than keeping the
+        // bank of the previous session
+        let mut dx7_bank = Dx7Bank::default();
+
+        while let Some((tag, data)) = state::read_chunk(input)? {
+            match &tag {
+                DX7_BANK_CHUNK => dx7_bank = Dx7Bank::parse(data).map_err(PluginError::Message)?,
+                USER_WAVETABLE_CHUNK => {
+                    params.wavetable.user = Some(Arc::new(
+                        UserWavetable::from_bytes(&data).map_err(PluginError::Message)?,
+                    ))
+                }
+                // Samples are loaded from where they were when the state was saved. An instrument
+                // that has since been moved shouldn't keep the rest of the state from loading.
+                SFZ_PATH_CHUNK => {
+                    params.sampler.instrument = Instrument::load(&String::from_utf8_lossy(&data))
+                        .ok()
+                        .map(Arc::new)
+                }
+                GRANULAR_SAMPLE_CHUNK => {
+                    let path = String::from_utf8_lossy(&data);
+                    params.granular.sample = Sample::load(Path::new(&*path)).ok().map(Arc::new)
+                }
+                ADDITIVE_SPECTRUM_CHUNK => params.additive.load_spectrum(&data),
+                SEQUENCER_PATTERN_CHUNK => params.sequencer.load_pattern(&data),
+                HARMONY_SHAPE_CHUNK => params.harmony.load_shape(&data),
+                _ => {}
+            }
+        }
+
+        *self.shared.dx7_bank.write().or(Err(PluginError::Message(
+            "Failed to acquire DX7 bank write lock",
+        )))? = dx7_bank;
+        *self.shared.params.write().or(Err(PluginError::Message(
             "Failed to acquire parameter write lock",
-        )))?;
-        let mut buf = [0; 4];
-        input.read_exact(&mut buf)?;
-        envelope.attack = f32::from_le_bytes(buf);
-        input.read_exact(&mut buf)?;
-        envelope.decay = f32::from_le_bytes(buf);
-        input.read_exact(&mut buf)?;
-        envelope.sustain = f32::from_le_bytes(buf);
-        input.read_exact(&mut buf)?;
-        envelope.release = f32::from_le_bytes(buf);
+        )))? = params;
         Ok(())
     }
 }
 
+impl<'a> PluginLatencyImpl for CrabHowlerMainThread<'a> {
+    fn get(&mut self) -> u32 {
+        self.latency
+    }
+}
+
 impl<'a> PluginAudioPortsImpl for CrabHowlerMainThread<'a> {
     fn count(&mut self, is_input: bool) -> u32 {
         if !is_input {
@@ -327,19 +613,15 @@ impl<'a> PluginAudioPortsImpl for CrabHowlerMainThread<'a> {
 }
 
 impl<'a> PluginNotePortsImpl for CrabHowlerMainThread<'a> {
-    fn count(&mut self, is_input: bool) -> u32 {
-        if is_input {
-            1
-        } else {
-            0
-        }
+    fn count(&mut self, _is_input: bool) -> u32 {
+        1
     }
 
     fn get(&mut self, index: u32, is_input: bool, writer: &mut NotePortInfoWriter) {
-        if is_input && index == 0 {
+        if index == 0 {
             writer.set(&NotePortInfo {
-                id: ClapId::new(1),
-                name: b"main",
+                id: ClapId::new(if is_input { 1 } else { 2 }),
+                name: if is_input { b"main" } else { b"output" },
                 preferred_dialect: Some(NoteDialect::Clap),
                 supported_dialects: NoteDialects::CLAP,
             })
@@ -376,8 +658,8 @@ impl<'a> PluginGuiImpl for CrabHowlerMainThread<'a> {
 
     fn get_size(&mut self) -> Option<clack_extensions::gui::GuiSize> {
         Some(clack_extensions::gui::GuiSize {
-            width: 400,
-            height: 200,
+            width: gui::WIDTH,
+            height: gui::HEIGHT,
         })
     }
 
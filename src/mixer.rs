@@ -0,0 +1,167 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    params::{choice, ParamGroup, ParamSpec, Unit},
+    random::Random,
+};
+
+choice! {
+    pub enum NoiseColor {
+        #[default]
+        White => "White",
+        Pink => "Pink",
+        Brown => "Brown",
+    }
+}
+
+choice! {
+    pub enum SubOctave {
+        #[default]
+        One => "-1",
+        Two => "-2",
+    }
+}
+
+choice! {
+    pub enum SubShape {
+        #[default]
+        Sine => "Sine",
+        Square => "Square",
+    }
+}
+
+/// Levels of the sources that are mixed in with the engine output of every voice.
+#[derive(Clone, Default)]
+pub struct MixerParams {
+    pub noise_color: NoiseColor,
+    pub noise_level: f32,
+    pub sub_octave: SubOctave,
+    pub sub_shape: SubShape,
+    pub sub_level: f32,
+}
+
+impl ParamGroup for MixerParams {
+    const COUNT: u32 = 5;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Noise Color", "Mixer", NoiseColor::NAMES)),
+            1 => Some(ParamSpec::new(
+                "Noise Level",
+                "Mixer",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            2 => Some(ParamSpec::choice("Sub Octave", "Mixer", SubOctave::NAMES)),
+            3 => Some(ParamSpec::choice("Sub Shape", "Mixer", SubShape::NAMES)),
+            4 => Some(ParamSpec::new(
+                "Sub Level",
+                "Mixer",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.noise_color.value()),
+            1 => Some(self.noise_level),
+            2 => Some(self.sub_octave.value()),
+            3 => Some(self.sub_shape.value()),
+            4 => Some(self.sub_level),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.noise_color = NoiseColor::from_value(value),
+            1 => self.noise_level = value,
+            2 => self.sub_octave = SubOctave::from_value(value),
+            3 => self.sub_shape = SubShape::from_value(value),
+            4 => self.sub_level = value,
+            _ => {}
+        }
+    }
+}
+
+/// Noise source with the filter state needed for the pink and brown colors.
+pub struct Noise {
+    random: Random,
+    pink: [f32; 3],
+    brown: f32,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            random: Random::new(seed),
+            pink: [0.0; 3],
+            brown: 0.0,
+        }
+    }
+
+    pub fn next_sample(&mut self, color: NoiseColor) -> f32 {
+        let white = self.random.next_bipolar();
+
+        // Paul Kellet's economy pink noise filter, accurate to within 0.05 dB above 10 Hz. Both
+        // filters always run so that switching colors doesn't start from silence.
+        self.pink[0] = 0.99765 * self.pink[0] + white * 0.099046;
+        self.pink[1] = 0.963 * self.pink[1] + white * 0.2965164;
+        self.pink[2] = 0.57 * self.pink[2] + white * 1.0526913;
+        self.brown = (self.brown + white * 0.02) / 1.02;
+
+        match color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => (self.pink.iter().sum::<f32>() + white * 0.1848) * 0.25,
+            NoiseColor::Brown => self.brown * 3.5,
+        }
+    }
+}
+
+/// Oscillator playing one or two octaves below the voice.
+#[derive(Default)]
+pub struct SubOscillator {
+    phase: f32,
+}
+
+impl SubOscillator {
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub fn next_sample(&mut self, params: &MixerParams, frequency: f32, sample_rate: f32) -> f32 {
+        let increment = match params.sub_octave {
+            SubOctave::One => frequency / 2.0,
+            SubOctave::Two => frequency / 4.0,
+        } / sample_rate;
+
+        let sample = match params.sub_shape {
+            SubShape::Sine => (self.phase * TAU).sin(),
+            SubShape::Square => {
+                let naive = if self.phase < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(self.phase, increment)
+                    - poly_blep((self.phase + 0.5) % 1.0, increment)
+            }
+        };
+        self.phase = (self.phase + increment) % 1.0;
+        sample
+    }
+}
+
+/// Correction that smooths the step of a discontinuity at phase 0 over the surrounding samples.
+pub fn poly_blep(phase: f32, increment: f32) -> f32 {
+    if phase < increment {
+        let t = phase / increment;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - increment {
+        let t = (phase - 1.0) / increment;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
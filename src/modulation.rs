@@ -0,0 +1,225 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::{
+    effects::Effect,
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+pub const CHORUS: usize = 0;
+pub const FLANGER: usize = 1;
+pub const PHASER: usize = 2;
+const NAMES: [&str; 3] = ["Chorus", "Flanger", "Phaser"];
+
+/// Longest delay the chorus and flanger sweep through, in milliseconds.
+const MAX_DELAY: f32 = 40.0;
+/// Allpass stages in each channel of the phaser.
+const STAGES: usize = 6;
+
+pub type ChorusParams = ModulationParams<CHORUS>;
+pub type FlangerParams = ModulationParams<FLANGER>;
+pub type PhaserParams = ModulationParams<PHASER>;
+
+/// Settings shared by the chorus, flanger and phaser, which differ only in what the LFO
+/// sweeps.
+#[derive(Clone)]
+pub struct ModulationParams<const KIND: usize> {
+    /// Frequency of the LFO, in Hz.
+    pub rate: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    /// Phase of the LFO in the right channel relative to the left, in degrees.
+    pub phase_offset: f32,
+}
+
+impl<const KIND: usize> Default for ModulationParams<KIND> {
+    fn default() -> Self {
+        let (rate, feedback) = match KIND {
+            CHORUS => (0.8, 0.0),
+            FLANGER => (0.2, 0.5),
+            _ => (0.4, 0.3),
+        };
+        Self {
+            rate,
+            depth: 0.5,
+            feedback,
+            phase_offset: 90.0,
+        }
+    }
+}
+
+impl<const KIND: usize> ParamGroup for ModulationParams<KIND> {
+    const COUNT: u32 = 4;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        let module = NAMES[KIND];
+        let name = |name: &str| format!("{module} {name}");
+        match index {
+            0 => Some(ParamSpec::new(
+                name("Rate"),
+                module,
+                0.01,
+                10.0,
+                Unit::Hertz,
+            )),
+            1 => Some(ParamSpec::new(
+                name("Depth"),
+                module,
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            2 => Some(ParamSpec::new(
+                name("Feedback"),
+                module,
+                0.0,
+                0.95,
+                Unit::Percent,
+            )),
+            3 => Some(ParamSpec::new(
+                name("Stereo Phase"),
+                module,
+                0.0,
+                180.0,
+                Unit::Degrees,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.rate),
+            1 => Some(self.depth),
+            2 => Some(self.feedback),
+            3 => Some(self.phase_offset),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.rate = value,
+            1 => self.depth = value,
+            2 => self.feedback = value,
+            3 => self.phase_offset = value,
+            _ => {}
+        }
+    }
+}
+
+/// The state of one channel.
+struct Channel {
+    buffer: Vec<f32>,
+    /// Output of the previous sample, which is fed back into the input.
+    feedback: f32,
+    allpasses: [f32; STAGES],
+}
+
+impl Channel {
+    /// Reads `delay` samples behind `position`, interpolating between samples.
+    fn read(&self, position: usize, delay: f32) -> f32 {
+        let length = self.buffer.len();
+        let offset = delay as usize;
+        let t = delay - offset as f32;
+        let a = self.buffer[(position + length - offset) % length];
+        let b = self.buffer[(position + 2 * length - offset - 1) % length];
+        a + (b - a) * t
+    }
+
+    /// Runs the allpass chain of the phaser, where `a` is the coefficient of every stage.
+    fn phase(&mut self, x: f32, a: f32) -> f32 {
+        self.allpasses.iter_mut().fold(x, |x, state| {
+            let y = a * x + *state;
+            *state = x - a * y;
+            y
+        })
+    }
+}
+
+/// A chorus, flanger or phaser, each driven by a sine LFO that is offset between the
+/// channels to widen the image.
+pub struct Modulation<const KIND: usize> {
+    sample_rate: f32,
+    /// Picks the settings of this kind out of the parameters.
+    settings: fn(&Parameters) -> &ModulationParams<KIND>,
+    channels: [Channel; 2],
+    position: usize,
+    /// Phase of the LFO, from 0 to 1.
+    phase: f32,
+}
+
+impl<const KIND: usize> Modulation<KIND> {
+    pub fn new(sample_rate: f32, settings: fn(&Parameters) -> &ModulationParams<KIND>) -> Self {
+        let length = if KIND == PHASER {
+            1
+        } else {
+            (MAX_DELAY * sample_rate / 1000.0) as usize + 2
+        };
+        let channel = || Channel {
+            buffer: vec![0.0; length],
+            feedback: 0.0,
+            allpasses: [0.0; STAGES],
+        };
+        Self {
+            sample_rate,
+            settings,
+            channels: [channel(), channel()],
+            position: 0,
+            phase: 0.0,
+        }
+    }
+}
+
+impl<const KIND: usize> Effect for Modulation<KIND> {
+    fn process(&mut self, params: &Parameters, _tempo: f64, left: &mut [f32], right: &mut [f32]) {
+        let settings = (self.settings)(params);
+        let increment = settings.rate / self.sample_rate;
+        let offset = settings.phase_offset / 360.0;
+        let ms = self.sample_rate / 1000.0;
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            self.phase = (self.phase + increment) % 1.0;
+            for (channel, (sample, phase)) in self
+                .channels
+                .iter_mut()
+                .zip([(&mut *left, self.phase), (&mut *right, self.phase + offset)])
+            {
+                let lfo = 0.5 + 0.5 * (TAU * phase).sin();
+                let input = *sample + channel.feedback * settings.feedback;
+                let wet = match KIND {
+                    CHORUS => {
+                        channel.buffer[self.position] = input;
+                        channel.read(self.position, (12.0 + 12.0 * settings.depth * lfo) * ms)
+                    }
+                    FLANGER => {
+                        channel.buffer[self.position] = input;
+                        channel.read(self.position, (0.5 + 7.0 * settings.depth * lfo) * ms)
+                    }
+                    _ => {
+                        // Sweep the notches exponentially, from 200 Hz up to 7 kHz
+                        let frequency = 200.0 * 35f32.powf(settings.depth * lfo);
+                        let t = (PI * frequency / self.sample_rate).tan();
+                        channel.phase(input, (t - 1.0) / (t + 1.0))
+                    }
+                };
+                channel.feedback = wet;
+                *sample = wet;
+            }
+            self.position = (self.position + 1) % self.channels[0].buffer.len();
+        }
+    }
+
+    fn tail(&self, params: &Parameters, _tempo: f64) -> f32 {
+        // Time until the feedback has fallen by 60 dB, going around the loop at its longest
+        let time = match KIND {
+            CHORUS => 0.024,
+            FLANGER => 0.0075,
+            // Each allpass stage delays the lowest notch by about 1.6 ms
+            _ => 0.01,
+        };
+        match (self.settings)(params).feedback {
+            0.0 => time,
+            feedback => time * (1.0 + (0.001f32).ln() / feedback.ln()),
+        }
+    }
+}
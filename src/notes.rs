@@ -0,0 +1,105 @@
+use clack_plugin::events::{
+    event_types::{NoteOffEvent, NoteOnEvent},
+    Match, Pckn,
+};
+
+/// A note generated inside the plugin, to be passed on to the oscillators.
+pub enum NoteEvent {
+    On(NoteOnEvent),
+    Off(NoteOffEvent),
+}
+
+impl NoteEvent {
+    /// Generated notes have no id of their own, so they are matched by channel and key.
+    pub fn on(channel: u16, key: u16, velocity: f64) -> Self {
+        NoteEvent::On(NoteOnEvent::new(
+            0,
+            Pckn::new(0u16, channel, key, Match::All),
+            velocity,
+        ))
+    }
+
+    pub fn off(channel: u16, key: u16) -> Self {
+        NoteEvent::Off(NoteOffEvent::new(
+            0,
+            Pckn::new(0u16, channel, key, Match::All),
+            0.0,
+        ))
+    }
+
+    /// Places the note at sample `time` of the block.
+    pub fn at(self, time: u32) -> Self {
+        match self {
+            NoteEvent::On(event) => {
+                NoteEvent::On(NoteOnEvent::new(time, event.pckn(), event.velocity()))
+            }
+            NoteEvent::Off(event) => {
+                NoteEvent::Off(NoteOffEvent::new(time, event.pckn(), event.velocity()))
+            }
+        }
+    }
+}
+
+/// Whether an event addressed to `target` applies to the note `note`. Wildcards in the target
+/// match any channel, key or note id.
+pub fn applies_to(target: &Pckn, note: &Pckn) -> bool {
+    fn matches<T: PartialEq>(target: &Match<T>, value: &Match<T>) -> bool {
+        *target == Match::All || target == value
+    }
+    matches(&target.channel, &note.channel)
+        && matches(&target.key, &note.key)
+        && matches(&target.note_id, &note.note_id)
+}
+
+/// Position of a note generator in beats. It follows the transport of the host while that is
+/// playing, and runs on its own otherwise.
+pub struct Clock {
+    sample_rate: f32,
+    pub beat: f64,
+    /// Whether the position came from the host at the start of this block.
+    pub synced: bool,
+}
+
+impl Clock {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            beat: 0.0,
+            synced: false,
+        }
+    }
+
+    pub fn sync(&mut self, position: Option<f64>) {
+        self.synced = position.is_some();
+        if let Some(position) = position {
+            self.beat = position;
+        }
+    }
+
+    pub fn advance(&mut self, tempo: f64, frames: usize) {
+        self.beat += frames as f64 * tempo / 60.0 / self.sample_rate as f64;
+    }
+
+    /// Whole samples from now until `beat`, rounded up so that nothing is played early.
+    pub fn samples_until(&self, tempo: f64, beat: f64) -> f64 {
+        let beats_per_sample = tempo / 60.0 / self.sample_rate as f64;
+        ((beat - self.beat) / beats_per_sample - 1e-6)
+            .ceil()
+            .max(0.0)
+    }
+
+    /// The first line of a grid of `length` beats at or after the current position.
+    pub fn next_line(&self, length: f64) -> f64 {
+        (self.beat / length - 1e-9).ceil() * length
+    }
+
+    /// Where a pattern that starts now places its first step: on the grid while the host is
+    /// playing, and right away otherwise.
+    pub fn start(&self, length: f64) -> f64 {
+        if self.synced {
+            self.next_line(length)
+        } else {
+            self.beat
+        }
+    }
+}
@@ -3,103 +3,243 @@ use clack_plugin::events::{
     Match,
 };
 
-use crate::adsr::{ADSRState, ADSR};
+use crate::{
+    additive::AdditiveVoice,
+    adsr::{ADSRState, ADSR},
+    analog::AnalogVoice,
+    dx7::Dx7Voice,
+    filter::{Filter, Lane},
+    fm::FmVoice,
+    granular::GranularVoice,
+    mixer::{Noise, SubOscillator},
+    params::{choice, ParamGroup, ParamSpec, Parameters},
+    pluck::PluckVoice,
+    sampler::SamplerVoice,
+    shaper::{Placement, Shaper},
+    wavetable::WavetableVoice,
+};
 
 pub trait Oscillator {
-    fn handle_note_on(&mut self, envelope: &super::Envelope, event: &NoteOnEvent);
+    fn handle_note_on(&mut self, params: &Parameters, event: &NoteOnEvent);
     fn handle_note_off(&mut self, event: &NoteOffEvent);
-    fn process(&mut self, left: &mut [f32], right: &mut [f32]);
+    /// Adds the output of all active voices to `left` and `right`, with the filters moved by
+    /// the modulation lane of the sequencer.
+    fn process(&mut self, params: &Parameters, lane: Lane, left: &mut [f32], right: &mut [f32]);
     fn is_active(&self) -> bool;
 }
 
-pub struct Voice {
+/// The sound source of a single voice. Voice allocation, note tracking and the amplitude
+/// envelope are shared between all generators through [`Polyphonic`].
+pub trait Generator {
+    fn start(&mut self, params: &Parameters, key: u16, velocity: f32);
+    fn release(&mut self) {}
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32;
+
+    /// Generators that shape their own amplitude bypass the shared envelope and velocity
+    /// response, and end the voice through [`Generator::is_finished`] instead.
+    fn has_envelope(&self) -> bool {
+        false
+    }
+
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
+
+choice! {
+    pub enum Engine {
+        #[default]
+        Analog => "Analog",
+        Fm => "FM",
+        Dx7 => "DX7",
+        Wavetable => "Wavetable",
+        Pluck => "Pluck",
+        Sampler => "Sampler",
+        Granular => "Granular",
+        Additive => "Additive",
+    }
+}
+
+impl Engine {
+    pub fn oscillator(self, sample_rate: f32) -> Box<dyn Oscillator + Send> {
+        match self {
+            Engine::Analog => Box::new(Polyphonic::new(sample_rate, AnalogVoice::default)),
+            Engine::Fm => Box::new(Polyphonic::new(sample_rate, FmVoice::default)),
+            Engine::Dx7 => Box::new(Polyphonic::new(sample_rate, Dx7Voice::default)),
+            Engine::Wavetable => Box::new(Polyphonic::new(sample_rate, WavetableVoice::default)),
+            Engine::Pluck => Box::new(Polyphonic::new(sample_rate, PluckVoice::default)),
+            Engine::Sampler => Box::new(Polyphonic::new(sample_rate, SamplerVoice::default)),
+            Engine::Granular => Box::new(Polyphonic::new(sample_rate, GranularVoice::default)),
+            Engine::Additive => Box::new(Polyphonic::new(sample_rate, AdditiveVoice::default)),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct OscillatorParams {
+    pub engine: Engine,
+}
+
+impl ParamGroup for OscillatorParams {
+    const COUNT: u32 = 1;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Engine", "Oscillator", Engine::NAMES)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.engine.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        if index == 0 {
+            self.engine = Engine::from_value(value);
+        }
+    }
+}
+
+pub fn key_frequency(key: f32) -> f32 {
+    440.0 * 2.0f32.powf((key - 57.0) / 12.0)
+}
+
+pub struct Voice<G> {
     channel: u16,
     key: u16,
     note_id: Option<u32>,
     frequency: f32,
-    phase: f32,
     velocity: f32,
     adsr: ADSR,
+    generator: G,
+    noise: Noise,
+    sub: SubOscillator,
+    filter: Filter,
+    shaper: Shaper,
 }
 
-impl Voice {
-    pub fn next_sample(&mut self, sample_rate: f32) -> f32 {
-        let increment = self.frequency / sample_rate;
-        let sample = (self.phase * std::f32::consts::TAU).sin();
-        self.phase = (self.phase + increment) % 1.0;
-        sample
+impl<G> Voice<G> {
+    fn new(generator: G, seed: u32) -> Self {
+        Self {
+            channel: 0,
+            key: 0,
+            note_id: None,
+            frequency: 0.0,
+            velocity: 0.0,
+            adsr: ADSR::default(),
+            generator,
+            noise: Noise::new(seed),
+            sub: SubOscillator::default(),
+            filter: Filter::default(),
+            shaper: Shaper::default(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.adsr.state != ADSRState::Ended
     }
 }
 
-pub struct SineOscillator {
+pub struct Polyphonic<G> {
     sample_rate: f32,
-    voices: [Option<Voice>; 16],
+    voices: [Voice<G>; 16],
 }
 
-impl SineOscillator {
-    pub fn new(sample_rate: f32) -> Self {
+impl<G: Generator> Polyphonic<G> {
+    pub fn new(sample_rate: f32, generator: impl Fn() -> G) -> Self {
         Self {
             sample_rate,
-            voices: Default::default(),
+            voices: std::array::from_fn(|index| Voice::new(generator(), index as u32 + 1)),
         }
     }
 }
 
-impl Oscillator for SineOscillator {
-    fn handle_note_on(&mut self, envelope: &super::Envelope, event: &NoteOnEvent) {
+impl<G: Generator> Oscillator for Polyphonic<G> {
+    fn handle_note_on(&mut self, params: &Parameters, event: &NoteOnEvent) {
         if let (Match::Specific(channel), Match::Specific(key)) = (event.channel(), event.key()) {
-            if let Some(voice) = self.voices.iter_mut().find(|voice| voice.is_none()) {
-                *voice = Some(Voice {
-                    channel,
-                    key,
-                    note_id: event.note_id().into_specific(),
-                    frequency: 440.0 * 2.0f32.powf((key as f32 - 57.0) / 12.0),
-                    phase: 0.0,
-                    velocity: event.velocity() as f32,
-                    adsr: ADSR::new(envelope.clone()),
-                });
+            if let Some(voice) = self.voices.iter_mut().find(|voice| !voice.is_active()) {
+                voice.channel = channel;
+                voice.key = key;
+                voice.note_id = event.note_id().into_specific();
+                voice.frequency = key_frequency(key as f32);
+                voice.velocity = event.velocity() as f32;
+                voice.adsr = ADSR::new(params.envelope.clone());
+                voice.generator.start(params, key, voice.velocity);
+                voice.sub.reset();
+                voice.filter.reset();
+                voice.shaper.reset();
             }
         }
     }
 
     fn handle_note_off(&mut self, event: &NoteOffEvent) {
-        if let Some(voice) = self.voices.iter_mut().flatten().find(|voice| {
-            event.channel().as_specific() == Some(&voice.channel)
+        if let Some(voice) = self.voices.iter_mut().find(|voice| {
+            voice.is_active()
+                && !matches!(voice.adsr.state, ADSRState::Release(_))
+                && event.channel().as_specific() == Some(&voice.channel)
                 && event.key().as_specific() == Some(&voice.key)
                 && event.note_id().as_specific() == voice.note_id.as_ref()
         }) {
             voice.adsr.release();
+            voice.generator.release();
         }
     }
 
-    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
-        left.fill(0.0);
-        right.fill(0.0);
-
-        // Turn off voices that have reached the end of their release phase
-        self.voices
-            .iter_mut()
-            .filter(|voice| match voice {
-                Some(voice) => voice.adsr.state == ADSRState::Ended,
-                None => false,
-            })
-            .for_each(|voice| *voice = None);
-
-        // Let's create a small vector to hold all active voices to (maybe?) increase performance a bit
-        let mut active_voices = self.voices.iter_mut().flatten().collect::<Vec<_>>();
-
-        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
-            for voice in &mut active_voices {
-                let gain = 10f32.powf(voice.velocity * voice.adsr.process(self.sample_rate) - 1.0);
-                let sample = voice.next_sample(self.sample_rate) * gain;
-
-                *left += sample;
-                *right += sample;
+    fn process(&mut self, params: &Parameters, lane: Lane, left: &mut [f32], right: &mut [f32]) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.is_active()) {
+            for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+                if !voice.is_active() {
+                    break;
+                }
+
+                let gain = if voice.generator.has_envelope() {
+                    1.0
+                } else {
+                    10f32.powf(voice.velocity * voice.adsr.process(self.sample_rate) - 1.0)
+                };
+                let mixer = &params.mixer;
+                let mut sample =
+                    voice
+                        .generator
+                        .next_sample(params, voice.frequency, self.sample_rate);
+                if mixer.sub_level > 0.0 {
+                    sample += voice
+                        .sub
+                        .next_sample(mixer, voice.frequency, self.sample_rate)
+                        * mixer.sub_level;
+                }
+                if mixer.noise_level > 0.0 {
+                    sample += voice.noise.next_sample(mixer.noise_color) * mixer.noise_level;
+                }
+
+                let shaper = &params.shaper;
+                if shaper.placement == Placement::PreFilter {
+                    sample = voice.shaper.process(shaper, 0, sample);
+                }
+                let (mut left_sample, mut right_sample) =
+                    voice
+                        .filter
+                        .process(&params.filter, lane, self.sample_rate, sample);
+                if shaper.placement == Placement::PostFilter {
+                    left_sample = voice.shaper.process(shaper, 0, left_sample);
+                    right_sample = voice.shaper.process(shaper, 1, right_sample);
+                }
+
+                *left += left_sample * gain;
+                *right += right_sample * gain;
+
+                if voice.generator.is_finished() {
+                    voice.adsr.state = ADSRState::Ended;
+                }
             }
         }
     }
 
     fn is_active(&self) -> bool {
-        self.voices.iter().any(Option::is_some)
+        self.voices.iter().any(Voice::is_active)
     }
 }
@@ -0,0 +1,115 @@
+use std::{f32::consts::PI, sync::OnceLock};
+
+/// Length of the half-band filter used by each 2x stage.
+const TAPS: usize = 31;
+
+/// Windowed-sinc half-band lowpass, cutting off at a quarter of the oversampled rate.
+fn coefficients() -> &'static [f32; TAPS] {
+    static COEFFICIENTS: OnceLock<[f32; TAPS]> = OnceLock::new();
+    COEFFICIENTS.get_or_init(|| {
+        let center = (TAPS / 2) as f32;
+        std::array::from_fn(|i| {
+            let x = i as f32 - center;
+            let sinc = if x == 0.0 {
+                0.5
+            } else {
+                (0.5 * PI * x).sin() / (PI * x)
+            };
+            let t = i as f32 / (TAPS - 1) as f32;
+            let blackman = 0.42 - 0.5 * (2.0 * PI * t).cos() + 0.08 * (4.0 * PI * t).cos();
+            sinc * blackman
+        })
+    })
+}
+
+/// A FIR half-band filter, with the history stored twice so that the taps can always be read
+/// as one contiguous slice.
+#[derive(Clone)]
+struct HalfBand {
+    coefficients: &'static [f32; TAPS],
+    history: [f32; 2 * TAPS],
+    position: usize,
+}
+
+impl Default for HalfBand {
+    fn default() -> Self {
+        Self {
+            coefficients: coefficients(),
+            history: [0.0; 2 * TAPS],
+            position: 0,
+        }
+    }
+}
+
+impl HalfBand {
+    fn push(&mut self, sample: f32) {
+        self.position = (self.position + 1) % TAPS;
+        self.history[self.position] = sample;
+        self.history[self.position + TAPS] = sample;
+    }
+
+    fn output(&self) -> f32 {
+        // The newest sample is at `position + TAPS`, and the oldest right after `position`
+        let taps = &self.history[self.position + 1..self.position + 1 + TAPS];
+        taps.iter()
+            .rev()
+            .zip(self.coefficients)
+            .map(|(sample, coefficient)| sample * coefficient)
+            .sum()
+    }
+
+    fn upsample(&mut self, sample: f32) -> [f32; 2] {
+        // Zero stuffing halves the level, which the gain of 2 makes up for
+        self.push(sample * 2.0);
+        let first = self.output();
+        self.push(0.0);
+        [first, self.output()]
+    }
+
+    fn downsample(&mut self, samples: [f32; 2]) -> f32 {
+        self.push(samples[0]);
+        self.push(samples[1]);
+        self.output()
+    }
+}
+
+/// Runs a nonlinear process at 2 or 4 times the sample rate, so that the harmonics it adds
+/// are filtered out instead of folding back into the audible range.
+#[derive(Clone, Default)]
+pub struct Oversampler {
+    up: [HalfBand; 2],
+    down: [HalfBand; 2],
+}
+
+impl Oversampler {
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Processes one sample through `process` at `2^stages` times the sample rate, where
+    /// `stages` is at most 2.
+    pub fn process(
+        &mut self,
+        stages: u32,
+        sample: f32,
+        mut process: impl FnMut(f32) -> f32,
+    ) -> f32 {
+        let ([up, inner_up], [down, inner_down]) = (&mut self.up, &mut self.down);
+        match stages {
+            0 => process(sample),
+            1 => {
+                let [a, b] = up.upsample(sample);
+                down.downsample([process(a), process(b)])
+            }
+            _ => {
+                let mut inner = |sample: f32| {
+                    let [a, b] = inner_up.upsample(sample);
+                    inner_down.downsample([process(a), process(b)])
+                };
+                let [a, b] = up.upsample(sample);
+                let output = [inner(a), inner(b)];
+                down.downsample(output)
+            }
+        }
+    }
+}
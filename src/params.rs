@@ -0,0 +1,362 @@
+use std::{
+    ffi::CStr,
+    fmt::Write as _,
+    io::{self, Read, Write},
+};
+
+use clack_plugin::events::event_types::{ParamModEvent, ParamValueEvent};
+
+use crate::{
+    additive::AdditiveParams,
+    analog::AnalogParams,
+    arpeggiator::ArpeggiatorParams,
+    bitcrusher::BitcrusherParams,
+    delay::DelayParams,
+    dx7::Dx7Params,
+    dynamics::DynamicsParams,
+    effects::EffectsParams,
+    envelope::Envelope,
+    eq::EqParams,
+    filter::FilterParams,
+    fm::FmParams,
+    granular::GranularParams,
+    harmony::HarmonyParams,
+    mixer::MixerParams,
+    modulation::{ChorusParams, FlangerParams, PhaserParams},
+    oscillator::OscillatorParams,
+    pluck::PluckParams,
+    reverb::ReverbParams,
+    sampler::SamplerParams,
+    sequencer::SequencerParams,
+    shaper::ShaperParams,
+    thru::ThruParams,
+    wavetable::WavetableParams,
+};
+
+/// Describes how a parameter value is displayed to, and parsed from, the user.
+#[derive(Clone, Copy)]
+pub enum Unit {
+    Seconds,
+    Milliseconds,
+    Percent,
+    Ratio,
+    Hertz,
+    Integer,
+    Semitones,
+    Cents,
+    Decibels,
+    Degrees,
+    Choice(&'static [&'static str]),
+}
+
+impl Unit {
+    pub fn is_stepped(&self) -> bool {
+        matches!(self, Unit::Integer | Unit::Semitones | Unit::Choice(_))
+    }
+
+    pub fn format(&self, value: f64, writer: &mut impl std::fmt::Write) -> std::fmt::Result {
+        match self {
+            Unit::Seconds => write!(writer, "{:.2} s", value),
+            Unit::Milliseconds => write!(writer, "{:.0} ms", value),
+            Unit::Percent => write!(writer, "{:.2} %", value * 100f64),
+            Unit::Ratio => write!(writer, "{:.2}x", value),
+            Unit::Hertz => write!(writer, "{:.1} Hz", value),
+            Unit::Integer => write!(writer, "{}", value.round()),
+            Unit::Semitones => write!(writer, "{:+} st", value.round()),
+            Unit::Cents => write!(writer, "{:+.0} ct", value),
+            Unit::Decibels => write!(writer, "{:.1} dB", value),
+            Unit::Degrees => write!(writer, "{:.0}°", value),
+            Unit::Choice(names) => names
+                .get(value.round() as usize)
+                .ok_or(std::fmt::Error)
+                .and_then(|name| writer.write_str(name)),
+        }
+    }
+
+    pub fn parse(&self, text: &CStr) -> Option<f64> {
+        let input = text.to_str().ok()?.trim();
+        if let Unit::Choice(names) = self {
+            if let Some(index) = names
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(input))
+            {
+                return Some(index as f64);
+            }
+        }
+
+        let scale = if matches!(self, Unit::Percent) {
+            0.01
+        } else {
+            1.0
+        };
+        let suffix_idx = input
+            .find(|c: char| !c.is_numeric() && !matches!(c, '.' | ',' | '-' | '+'))
+            .unwrap_or(input.len());
+        input[..suffix_idx].parse().map(|v: f64| v * scale).ok()
+    }
+}
+
+pub struct ParamSpec {
+    pub name: String,
+    pub module: String,
+    pub min: f32,
+    pub max: f32,
+    pub unit: Unit,
+    pub modulatable: bool,
+}
+
+impl ParamSpec {
+    pub fn new(
+        name: impl Into<String>,
+        module: impl Into<String>,
+        min: f32,
+        max: f32,
+        unit: Unit,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            module: module.into(),
+            min,
+            max,
+            unit,
+            modulatable: false,
+        }
+    }
+
+    /// Marks the parameter as accepting host modulation through [`ParamGroup::modulate`].
+    pub fn modulatable(mut self) -> Self {
+        self.modulatable = true;
+        self
+    }
+
+    pub fn choice(
+        name: impl Into<String>,
+        module: impl Into<String>,
+        names: &'static [&'static str],
+    ) -> Self {
+        Self::new(
+            name,
+            module,
+            0.0,
+            (names.len() - 1) as f32,
+            Unit::Choice(names),
+        )
+    }
+}
+
+/// A set of related parameters, addressed by their index within the group.
+pub trait ParamGroup: Default {
+    const COUNT: u32;
+
+    fn spec(index: u32) -> Option<ParamSpec>;
+    fn get(&self, index: u32) -> Option<f32>;
+    fn set(&mut self, index: u32, value: f32);
+
+    /// Applies a modulation offset to a parameter declared as modulatable. The offset is kept
+    /// apart from the value so that it never ends up in automation or the saved state.
+    fn modulate(&mut self, _index: u32, _amount: f32) {}
+}
+
+/// Declares an enum that is exposed to the host as a stepped parameter.
+macro_rules! choice {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($(#[$vmeta:meta])* $variant:ident => $label:literal),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+        $vis enum $name {
+            $($(#[$vmeta])* $variant),*
+        }
+
+        impl $name {
+            pub const NAMES: &'static [&'static str] = &[$($label),*];
+            pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+
+            pub fn from_value(value: f32) -> Self {
+                Self::ALL[(value.round().max(0.0) as usize).min(Self::ALL.len() - 1)]
+            }
+
+            pub fn value(self) -> f32 {
+                self as usize as f32
+            }
+        }
+    };
+}
+pub(crate) use choice;
+
+choice! {
+    pub enum Toggle {
+        #[default]
+        Off => "Off",
+        On => "On",
+    }
+}
+
+/// Declares the full parameter set of the plugin. Every group is given a base id so that ids
+/// stay stable for hosts and saved states when groups grow.
+macro_rules! parameters {
+    ($($field:ident: $group:ty = $base:literal),* $(,)?) => {
+        #[derive(Clone, Default)]
+        pub struct Parameters {
+            $(pub $field: $group,)*
+        }
+
+        impl Parameters {
+            pub fn count() -> u32 {
+                0 $(+ <$group>::COUNT)*
+            }
+
+            /// Returns the id, spec and default value of the parameter at the given host index.
+            #[allow(unused_assignments)]
+            pub fn info(index: u32) -> Option<(u32, ParamSpec, f32)> {
+                let mut start = 0;
+                $(
+                    if let Some(index) = index.checked_sub(start).filter(|index| *index < <$group>::COUNT) {
+                        let default = <$group>::default().get(index)?;
+                        return <$group>::spec(index).map(|spec| ($base + index, spec, default));
+                    }
+                    start += <$group>::COUNT;
+                )*
+                None
+            }
+
+            pub fn spec(id: u32) -> Option<ParamSpec> {
+                $(
+                    if let Some(index) = id.checked_sub($base).filter(|index| *index < <$group>::COUNT) {
+                        return <$group>::spec(index);
+                    }
+                )*
+                None
+            }
+
+            pub fn get(&self, id: u32) -> Option<f32> {
+                $(
+                    if let Some(index) = id.checked_sub($base).filter(|index| *index < <$group>::COUNT) {
+                        return self.$field.get(index);
+                    }
+                )*
+                None
+            }
+
+            pub fn set(&mut self, id: u32, value: f32) {
+                $(
+                    if let Some(index) = id.checked_sub($base).filter(|index| *index < <$group>::COUNT) {
+                        return self.$field.set(index, value);
+                    }
+                )*
+            }
+
+            pub fn modulate(&mut self, id: u32, amount: f32) {
+                $(
+                    if let Some(index) = id.checked_sub($base).filter(|index| *index < <$group>::COUNT) {
+                        return self.$field.modulate(index, amount);
+                    }
+                )*
+            }
+        }
+    };
+}
+
+parameters! {
+    envelope: Envelope = 0,
+    oscillator: OscillatorParams = 100,
+    fm: FmParams = 1000,
+    dx7: Dx7Params = 2000,
+    wavetable: WavetableParams = 3000,
+    mixer: MixerParams = 4000,
+    analog: AnalogParams = 5000,
+    pluck: PluckParams = 6000,
+    sampler: SamplerParams = 7000,
+    granular: GranularParams = 8000,
+    additive: AdditiveParams = 9000,
+    filter: FilterParams = 10000,
+    shaper: ShaperParams = 11000,
+    effects: EffectsParams = 12000,
+    delay: DelayParams = 13000,
+    reverb: ReverbParams = 14000,
+    chorus: ChorusParams = 15000,
+    flanger: FlangerParams = 16000,
+    phaser: PhaserParams = 17000,
+    dynamics: DynamicsParams = 18000,
+    eq: EqParams = 19000,
+    bitcrusher: BitcrusherParams = 20000,
+    arpeggiator: ArpeggiatorParams = 21000,
+    sequencer: SequencerParams = 22000,
+    harmony: HarmonyParams = 23000,
+    thru: ThruParams = 24000,
+}
+
+const STATE_MAGIC: &[u8; 4] = b"CRAB";
+
+impl Parameters {
+    pub fn handle_event(&mut self, event: &ParamValueEvent) {
+        if let Some(id) = event.param_id() {
+            self.set(id.into(), event.value() as f32);
+        }
+    }
+
+    pub fn handle_mod_event(&mut self, event: &ParamModEvent) {
+        if let Some(id) = event.param_id() {
+            self.modulate(id.into(), event.amount() as f32);
+        }
+    }
+
+    pub fn value_to_text(
+        id: u32,
+        value: f64,
+        writer: &mut impl std::fmt::Write,
+    ) -> std::fmt::Result {
+        Self::spec(id)
+            .ok_or(std::fmt::Error)?
+            .unit
+            .format(value, writer)
+    }
+
+    pub fn text_to_value(id: u32, text: &CStr) -> Option<f64> {
+        Self::spec(id)?.unit.parse(text)
+    }
+
+    pub fn save(&self, output: &mut impl Write) -> io::Result<()> {
+        output.write_all(STATE_MAGIC)?;
+        output.write_all(&Self::count().to_le_bytes())?;
+        for (id, _, _) in (0..Self::count()).filter_map(Self::info) {
+            let value = self.get(id).unwrap_or_default();
+            output.write_all(&id.to_le_bytes())?;
+            output.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn load(&mut self, input: &mut impl Read) -> io::Result<()> {
+        let mut buf = [0; 4];
+        input.read_exact(&mut buf)?;
+
+        // States saved before the parameter set grew only contain the envelope
+        if &buf != STATE_MAGIC {
+            self.envelope.attack = f32::from_le_bytes(buf);
+            input.read_exact(&mut buf)?;
+            self.envelope.decay = f32::from_le_bytes(buf);
+            input.read_exact(&mut buf)?;
+            self.envelope.sustain = f32::from_le_bytes(buf);
+            input.read_exact(&mut buf)?;
+            self.envelope.release = f32::from_le_bytes(buf);
+            return Ok(());
+        }
+
+        input.read_exact(&mut buf)?;
+        for _ in 0..u32::from_le_bytes(buf) {
+            input.read_exact(&mut buf)?;
+            let id = u32::from_le_bytes(buf);
+            input.read_exact(&mut buf)?;
+            self.set(id, f32::from_le_bytes(buf));
+        }
+        Ok(())
+    }
+}
+
+/// Formats a value for display in the GUI, falling back to the plain number.
+pub fn display(unit: Unit, value: f32) -> String {
+    let mut text = String::new();
+    if unit.format(value as f64, &mut text).is_err() {
+        let _ = write!(text, "{}", value);
+    }
+    text
+}
@@ -0,0 +1,207 @@
+use crate::{
+    oscillator::Generator,
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+    random::Random,
+};
+
+/// Length of the delay line, enough for a period of 11.7 Hz at 96 kHz.
+const MAX_DELAY: usize = 8192;
+/// Level below which a string is considered to have stopped ringing.
+const SILENCE: f32 = 1e-4;
+
+#[derive(Clone)]
+pub struct PluckParams {
+    /// Cutoff of the lowpass applied to the noise burst that excites the string.
+    pub brightness: f32,
+    /// Where along the string it is plucked, as a fraction of its length from the bridge.
+    pub pick_position: f32,
+    /// Strength of the lowpass in the feedback loop, which makes high partials die out first.
+    pub damping: f32,
+    /// Time for the string to decay by 60 dB.
+    pub decay: f32,
+    /// How much of the remaining decay is cut short when the note is released.
+    pub mute: f32,
+}
+
+impl Default for PluckParams {
+    fn default() -> Self {
+        Self {
+            brightness: 0.7,
+            pick_position: 0.13,
+            damping: 0.5,
+            decay: 4.0,
+            mute: 0.9,
+        }
+    }
+}
+
+impl ParamGroup for PluckParams {
+    const COUNT: u32 = 5;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new(
+                "Brightness",
+                "Pluck",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            1 => Some(ParamSpec::new(
+                "Pick Position",
+                "Pluck",
+                0.0,
+                0.5,
+                Unit::Percent,
+            )),
+            2 => Some(ParamSpec::new("Damping", "Pluck", 0.0, 1.0, Unit::Percent)),
+            3 => Some(ParamSpec::new("Decay", "Pluck", 0.05, 20.0, Unit::Seconds)),
+            4 => Some(ParamSpec::new("Mute", "Pluck", 0.0, 1.0, Unit::Percent)),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.brightness),
+            1 => Some(self.pick_position),
+            2 => Some(self.damping),
+            3 => Some(self.decay),
+            4 => Some(self.mute),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.brightness = value,
+            1 => self.pick_position = value,
+            2 => self.damping = value,
+            3 => self.decay = value,
+            4 => self.mute = value,
+            _ => {}
+        }
+    }
+}
+
+/// A Karplus-Strong string: a burst of noise circulating in a delay line tuned to the pitch of
+/// the note, losing a little energy and high end on every pass.
+pub struct PluckVoice {
+    random: Random,
+    delay: Vec<f32>,
+    position: usize,
+    velocity: f32,
+    /// Set on note-on, the delay line is filled with the excitation on the next sample, once
+    /// the period of the note is known.
+    excite: bool,
+    released: bool,
+    previous: f32,
+    allpass: (f32, f32),
+    dc_blocker: (f32, f32),
+    level: f32,
+}
+
+impl Default for PluckVoice {
+    fn default() -> Self {
+        Self {
+            random: Random::unique(),
+            delay: vec![0.0; MAX_DELAY],
+            position: 0,
+            velocity: 0.0,
+            excite: false,
+            released: false,
+            previous: 0.0,
+            allpass: (0.0, 0.0),
+            dc_blocker: (0.0, 0.0),
+            level: 0.0,
+        }
+    }
+}
+
+impl PluckVoice {
+    /// Fills the last `length` samples of the delay line with a filtered noise burst.
+    fn excite(&mut self, params: &PluckParams, length: usize) {
+        let cutoff = (params.brightness * (0.5 + 0.5 * self.velocity)).clamp(0.01, 1.0);
+        let mut lowpass = 0.0;
+        for i in 0..length {
+            lowpass += (self.random.next_bipolar() - lowpass) * cutoff;
+            // Halved, as the comb filter below can double the peak level
+            self.delay[(self.position + MAX_DELAY - length + i) % MAX_DELAY] =
+                lowpass * self.velocity * 0.5;
+        }
+
+        // Plucking at a point cancels the harmonics that have a node there, which is modelled
+        // by a comb filter over the burst. Running it backwards lets it work in place.
+        let pick = (params.pick_position * length as f32).round() as usize;
+        if pick > 0 {
+            for i in (pick..length).rev() {
+                let index = (self.position + MAX_DELAY - length + i) % MAX_DELAY;
+                self.delay[index] -= self.delay[(index + MAX_DELAY - pick) % MAX_DELAY];
+            }
+        }
+    }
+}
+
+impl Generator for PluckVoice {
+    fn start(&mut self, _params: &Parameters, _key: u16, velocity: f32) {
+        self.delay.fill(0.0);
+        self.velocity = velocity;
+        self.excite = true;
+        self.released = false;
+        self.previous = 0.0;
+        self.allpass = (0.0, 0.0);
+        self.dc_blocker = (0.0, 0.0);
+        self.level = 1.0;
+    }
+
+    fn release(&mut self) {
+        self.released = true;
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let pluck = &params.pluck;
+
+        // The loop lowpass delays the signal by `stretch` samples, and the allpass by the
+        // fractional part, which together with the delay line make up one period
+        let stretch = pluck.damping * 0.5;
+        let period = (sample_rate / frequency).clamp(2.0, (MAX_DELAY - 1) as f32);
+        let length = ((period - stretch - 0.1).floor() as usize).max(1);
+        let fraction = period - stretch - length as f32;
+        let coefficient = (1.0 - fraction) / (1.0 + fraction);
+
+        if self.excite {
+            self.excite = false;
+            self.excite(pluck, length);
+        }
+
+        let decay = if self.released {
+            pluck.decay * (1.0 - pluck.mute * 0.99)
+        } else {
+            pluck.decay
+        };
+        let gain = 0.001f32.powf(1.0 / (decay * frequency));
+
+        let input = self.delay[(self.position + MAX_DELAY - length) % MAX_DELAY];
+        let lowpass = (1.0 - stretch) * input + stretch * self.previous;
+        self.previous = input;
+        let allpass = coefficient * lowpass + self.allpass.0 - coefficient * self.allpass.1;
+        self.allpass = (lowpass, allpass);
+
+        let output = allpass * gain;
+        self.delay[self.position] = output;
+        self.position = (self.position + 1) % MAX_DELAY;
+
+        self.level = output.abs().max(self.level * (1.0 - 1.0 / period));
+        let sample = output - self.dc_blocker.0 + 0.995 * self.dc_blocker.1;
+        self.dc_blocker = (output, sample);
+        sample
+    }
+
+    fn has_envelope(&self) -> bool {
+        true
+    }
+
+    fn is_finished(&self) -> bool {
+        !self.excite && self.level < SILENCE
+    }
+}
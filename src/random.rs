@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A small xorshift generator, cheap enough to run per sample on the audio thread.
+#[derive(Clone)]
+pub struct Random(u32);
+
+impl Random {
+    pub fn new(seed: u32) -> Self {
+        // Xorshift gets stuck on zero, and small seeds take a while to get going
+        Self(seed.wrapping_mul(0x9e3779b9) | 1)
+    }
+
+    /// Creates a generator seeded differently from every other one created this way, so that
+    /// voices don't all produce the same sequence.
+    pub fn unique() -> Self {
+        static SEED: AtomicU32 = AtomicU32::new(1);
+        Self::new(SEED.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// Returns a value in the range 0 to 1.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Returns a value in the range -1 to 1.
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
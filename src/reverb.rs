@@ -0,0 +1,238 @@
+use crate::{
+    effects::Effect,
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+};
+
+/// Lengths of the feedback delay lines at full size, in milliseconds. They are chosen to
+/// share no common factors, so that their echoes don't pile up into audible patterns.
+const LINES: [f32; 8] = [37.1, 41.3, 47.9, 53.3, 61.7, 67.1, 73.7, 79.9];
+/// Lengths of the allpass filters that smear the input before it enters the feedback
+/// network, in milliseconds.
+const DIFFUSERS: [f32; 4] = [4.7, 3.6, 12.7, 9.3];
+const MAX_PREDELAY: f32 = 250.0;
+
+#[derive(Clone)]
+pub struct ReverbParams {
+    pub size: f32,
+    /// Time for the tail to fall by 60 dB, in seconds.
+    pub decay: f32,
+    /// Delay before the reverb starts, in milliseconds.
+    pub predelay: f32,
+    pub damping: f32,
+    pub width: f32,
+}
+
+impl Default for ReverbParams {
+    fn default() -> Self {
+        Self {
+            size: 0.7,
+            decay: 2.5,
+            predelay: 20.0,
+            damping: 0.4,
+            width: 1.0,
+        }
+    }
+}
+
+impl ParamGroup for ReverbParams {
+    const COUNT: u32 = 5;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new(
+                "Reverb Size",
+                "Reverb",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            1 => Some(ParamSpec::new(
+                "Reverb Decay",
+                "Reverb",
+                0.1,
+                20.0,
+                Unit::Seconds,
+            )),
+            2 => Some(ParamSpec::new(
+                "Reverb Pre-delay",
+                "Reverb",
+                0.0,
+                MAX_PREDELAY,
+                Unit::Milliseconds,
+            )),
+            3 => Some(ParamSpec::new(
+                "Reverb Damping",
+                "Reverb",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            4 => Some(ParamSpec::new(
+                "Reverb Width",
+                "Reverb",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.size),
+            1 => Some(self.decay),
+            2 => Some(self.predelay),
+            3 => Some(self.damping),
+            4 => Some(self.width),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.size = value,
+            1 => self.decay = value,
+            2 => self.predelay = value,
+            3 => self.damping = value,
+            4 => self.width = value,
+            _ => {}
+        }
+    }
+}
+
+/// A circular buffer, read at a fixed distance behind the write position.
+struct DelayLine {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1) + 1],
+            position: 0,
+        }
+    }
+
+    fn read(&self, delay: usize) -> f32 {
+        let length = self.buffer.len();
+        self.buffer[(self.position + length - delay.min(length - 1)) % length]
+    }
+
+    fn write(&mut self, sample: f32) {
+        self.position = (self.position + 1) % self.buffer.len();
+        self.buffer[self.position] = sample;
+    }
+}
+
+/// Schroeder allpass, which spreads a sound out in time without colouring it.
+struct Allpass {
+    line: DelayLine,
+    delay: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, x: f32) -> f32 {
+        const GAIN: f32 = 0.7;
+        let delayed = self.line.read(self.delay - 1);
+        let input = x + delayed * GAIN;
+        self.line.write(input);
+        delayed - input * GAIN
+    }
+}
+
+/// Feedback delay network of eight lines, mixed through a Hadamard matrix so that every line
+/// feeds all the others with equal energy.
+pub struct Reverb {
+    sample_rate: f32,
+    predelay: DelayLine,
+    diffusers: [Allpass; 4],
+    lines: [DelayLine; 8],
+    /// State of the lowpass filter in each line, which makes highs die out sooner.
+    damping: [f32; 8],
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let samples = |ms: f32| (ms * sample_rate / 1000.0) as usize;
+        Self {
+            sample_rate,
+            predelay: DelayLine::new(samples(MAX_PREDELAY)),
+            diffusers: DIFFUSERS.map(|ms| Allpass {
+                line: DelayLine::new(samples(ms)),
+                delay: samples(ms).max(1),
+            }),
+            lines: LINES.map(|ms| DelayLine::new(samples(ms))),
+            damping: [0.0; 8],
+        }
+    }
+}
+
+/// In-place fast Walsh-Hadamard transform, scaled to keep the energy unchanged.
+fn hadamard(values: &mut [f32; 8]) {
+    let mut span = 1;
+    while span < values.len() {
+        for start in (0..values.len()).step_by(span * 2) {
+            for i in start..start + span {
+                let (a, b) = (values[i], values[i + span]);
+                values[i] = a + b;
+                values[i + span] = a - b;
+            }
+        }
+        span *= 2;
+    }
+    let scale = 1.0 / (values.len() as f32).sqrt();
+    values.iter_mut().for_each(|value| *value *= scale);
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, params: &Parameters, _tempo: f64, left: &mut [f32], right: &mut [f32]) {
+        let reverb = &params.reverb;
+        let scale = 0.3 + 0.7 * reverb.size;
+        let delays = LINES.map(|ms| ((ms * scale * self.sample_rate / 1000.0) as usize).max(1));
+        // Each line loses 60 dB over the decay time, in proportion to its length
+        let gains =
+            delays.map(|delay| 10f32.powf(-3.0 * delay as f32 / (reverb.decay * self.sample_rate)));
+        let predelay = (reverb.predelay * self.sample_rate / 1000.0) as usize;
+        let damping = reverb.damping * 0.7;
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            self.predelay.write((*left + *right) * 0.5);
+            let input = self
+                .diffusers
+                .iter_mut()
+                .fold(self.predelay.read(predelay), |x, diffuser| {
+                    diffuser.process(x)
+                });
+
+            let mut outputs: [f32; 8] = std::array::from_fn(|i| self.lines[i].read(delays[i]));
+            let (first, second) = outputs.split_at(4);
+            let (wet_left, wet_right) = (first.iter().sum::<f32>(), second.iter().sum::<f32>());
+
+            hadamard(&mut outputs);
+            for (i, output) in outputs.iter().enumerate() {
+                let state = &mut self.damping[i];
+                *state = output * gains[i] * (1.0 - damping) + *state * damping;
+                // Alternating signs keep the input from summing in phase in every line
+                let sign = match i % 2 {
+                    0 => 1.0,
+                    _ => -1.0,
+                };
+                self.lines[i].write(*state + input * sign);
+            }
+
+            // Narrow the image by mixing the sides back towards the middle
+            let mid = (wet_left + wet_right) * 0.5;
+            let side = (wet_left - wet_right) * 0.5 * reverb.width;
+            let (wet_left, wet_right) = ((mid + side) * 0.5, (mid - side) * 0.5);
+
+            *left = wet_left;
+            *right = wet_right;
+        }
+    }
+
+    fn tail(&self, params: &Parameters, _tempo: f64) -> f32 {
+        params.reverb.decay + params.reverb.predelay / 1000.0
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use crate::{
+    oscillator::{key_frequency, Generator},
+    params::{ParamGroup, ParamSpec, Parameters, Unit},
+    sfz::{Instrument, LoopMode},
+};
+
+/// Number of regions that can be layered on a single note.
+const MAX_LAYERS: usize = 4;
+
+#[derive(Clone, Default)]
+pub struct SamplerParams {
+    pub transpose: f32,
+    pub instrument: Option<Arc<Instrument>>,
+}
+
+impl ParamGroup for SamplerParams {
+    const COUNT: u32 = 1;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::new(
+                "Transpose",
+                "Sampler",
+                -24.0,
+                24.0,
+                Unit::Semitones,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.transpose),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        if index == 0 {
+            self.transpose = value.round();
+        }
+    }
+}
+
+/// Playback state of one of the regions triggered by a note.
+#[derive(Clone, Copy, Default)]
+struct Layer {
+    active: bool,
+    region: usize,
+    position: f64,
+    /// Playback speed relative to the sample rate of the region, per Hz of the note.
+    pitch: f64,
+    gain: f32,
+}
+
+#[derive(Default)]
+pub struct SamplerVoice {
+    instrument: u32,
+    layers: [Layer; MAX_LAYERS],
+    released: bool,
+    /// Set when every region of the note is a one-shot, which plays to its end regardless of
+    /// the key being released, so the shared envelope is not applied.
+    one_shot: bool,
+}
+
+impl Generator for SamplerVoice {
+    fn start(&mut self, params: &Parameters, key: u16, velocity: f32) {
+        self.layers = Default::default();
+        self.released = false;
+        self.one_shot = false;
+        let Some(instrument) = &params.sampler.instrument else {
+            return;
+        };
+        self.instrument = instrument.id;
+
+        let key = key.min(127) as u8;
+        let velocity = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+        let regions = instrument
+            .regions
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| region.matches(key, velocity));
+        for (layer, (index, region)) in self.layers.iter_mut().zip(regions) {
+            *layer = Layer {
+                active: true,
+                region: index,
+                position: region.offset as f64,
+                pitch: 2f64.powf(region.tune as f64 / 1200.0)
+                    / key_frequency(region.root as f32) as f64,
+                gain: region.gain(key, velocity),
+            };
+        }
+
+        let mut active = self.layers.iter().filter(|layer| layer.active).peekable();
+        self.one_shot = active.peek().is_some()
+            && active.all(|layer| instrument.regions[layer.region].loop_mode == LoopMode::OneShot);
+        if self.one_shot {
+            // The same velocity response the shared envelope would have applied at full level
+            let gain = 10f32.powf(velocity as f32 / 127.0 - 1.0);
+            self.layers.iter_mut().for_each(|layer| layer.gain *= gain);
+        }
+    }
+
+    fn release(&mut self) {
+        if !self.one_shot {
+            self.released = true;
+        }
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let Some(instrument) = params
+            .sampler
+            .instrument
+            .as_ref()
+            .filter(|instrument| instrument.id == self.instrument)
+        else {
+            // The instrument was replaced, so the regions of the voice no longer exist
+            self.layers
+                .iter_mut()
+                .for_each(|layer| layer.active = false);
+            return 0.0;
+        };
+        let frequency = frequency as f64 * 2f64.powf(params.sampler.transpose as f64 / 12.0);
+
+        let mut sample = 0.0;
+        for layer in self.layers.iter_mut().filter(|layer| layer.active) {
+            let region = &instrument.regions[layer.region];
+            let looping = region.loops(self.released);
+            sample += region.read(layer.position, looping) * layer.gain;
+
+            layer.position +=
+                frequency * layer.pitch * region.sample.sample_rate as f64 / sample_rate as f64;
+            if looping && layer.position >= region.loop_end as f64 {
+                layer.position -= (region.loop_end - region.loop_start) as f64;
+            } else if layer.position >= region.sample.data.len() as f64 {
+                layer.active = false;
+            }
+        }
+        sample
+    }
+
+    fn has_envelope(&self) -> bool {
+        self.one_shot
+    }
+
+    fn is_finished(&self) -> bool {
+        self.layers.iter().all(|layer| !layer.active)
+    }
+}
@@ -0,0 +1,400 @@
+use clack_plugin::events::{
+    event_types::{NoteOffEvent, NoteOnEvent},
+    Match,
+};
+
+use crate::{
+    delay::Division,
+    filter::Lane,
+    notes::{Clock, NoteEvent},
+    params::{choice, ParamGroup, ParamSpec, Unit},
+};
+
+pub const MAX_STEPS: usize = 32;
+/// Most keys followed at once in transpose mode.
+const MAX_KEYS: usize = 128;
+/// How far the modulation lane moves the filter cutoff at full depth, in octaves.
+const CUTOFF_RANGE: f32 = 4.0;
+
+choice! {
+    pub enum SequencerMode {
+        #[default]
+        Off => "Off",
+        Transpose => "Transpose Held Notes",
+        Play => "Play With Transport",
+    }
+}
+
+choice! {
+    pub enum ModTarget {
+        #[default]
+        Off => "Off",
+        Cutoff => "Filter Cutoff",
+        Vowel => "Filter Vowel",
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Step {
+    /// Offset from the played or root key, in semitones.
+    pub pitch: f32,
+    /// Velocity of the note, where zero makes the step a rest.
+    pub velocity: f32,
+    /// Length of the note, relative to the step.
+    pub gate: f32,
+    /// Whether the note carries on into the next step. A tied note of the same pitch is not
+    /// played again.
+    pub tie: bool,
+    /// Value of the modulation lane, from -1 to 1.
+    pub modulation: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            pitch: 0.0,
+            velocity: 0.8,
+            gate: 0.5,
+            tie: false,
+            modulation: 0.0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SequencerParams {
+    pub mode: SequencerMode,
+    /// Number of steps in the pattern.
+    pub steps: f32,
+    pub rate: Division,
+    /// Key the pitches are relative to when playing along with the transport.
+    pub root: f32,
+    pub mod_target: ModTarget,
+    pub mod_depth: f32,
+    /// The steps, as edited in the pattern editor.
+    pub pattern: [Step; MAX_STEPS],
+}
+
+impl Default for SequencerParams {
+    fn default() -> Self {
+        Self {
+            mode: SequencerMode::Off,
+            steps: 16.0,
+            rate: Division::Sixteenth,
+            root: 60.0,
+            mod_target: ModTarget::Off,
+            mod_depth: 0.5,
+            pattern: [Step::default(); MAX_STEPS],
+        }
+    }
+}
+
+impl SequencerParams {
+    pub fn steps(&self) -> usize {
+        (self.steps.round() as usize).clamp(1, MAX_STEPS)
+    }
+
+    /// Serializes the pattern for the plugin state.
+    pub fn pattern_bytes(&self) -> Vec<u8> {
+        self.pattern
+            .iter()
+            .flat_map(|step| {
+                [
+                    step.pitch,
+                    step.velocity,
+                    step.gate,
+                    step.tie as u8 as f32,
+                    step.modulation,
+                ]
+            })
+            .flat_map(f32::to_le_bytes)
+            .collect()
+    }
+
+    pub fn load_pattern(&mut self, data: &[u8]) {
+        for (step, bytes) in self.pattern.iter_mut().zip(data.chunks_exact(20)) {
+            let value = |index: usize| {
+                let bytes = &bytes[index * 4..index * 4 + 4];
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            };
+            *step = Step {
+                pitch: value(0),
+                velocity: value(1),
+                gate: value(2),
+                tie: value(3) != 0.0,
+                modulation: value(4),
+            };
+        }
+    }
+}
+
+impl ParamGroup for SequencerParams {
+    const COUNT: u32 = 6;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice(
+                "Sequencer",
+                "Sequencer",
+                SequencerMode::NAMES,
+            )),
+            1 => Some(ParamSpec::new(
+                "Seq Steps",
+                "Sequencer",
+                1.0,
+                MAX_STEPS as f32,
+                Unit::Integer,
+            )),
+            2 => Some(ParamSpec::choice("Seq Rate", "Sequencer", Division::NAMES)),
+            3 => Some(ParamSpec::new(
+                "Seq Root",
+                "Sequencer",
+                0.0,
+                127.0,
+                Unit::Integer,
+            )),
+            4 => Some(ParamSpec::choice(
+                "Seq Mod Target",
+                "Sequencer",
+                ModTarget::NAMES,
+            )),
+            5 => Some(ParamSpec::new(
+                "Seq Mod Depth",
+                "Sequencer",
+                0.0,
+                1.0,
+                Unit::Percent,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.mode.value()),
+            1 => Some(self.steps),
+            2 => Some(self.rate.value()),
+            3 => Some(self.root),
+            4 => Some(self.mod_target.value()),
+            5 => Some(self.mod_depth),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.mode = SequencerMode::from_value(value),
+            1 => self.steps = value,
+            2 => self.rate = Division::from_value(value),
+            3 => self.root = value,
+            4 => self.mod_target = ModTarget::from_value(value),
+            5 => self.mod_depth = value,
+            _ => {}
+        }
+    }
+}
+
+/// A note started by the sequencer.
+struct Playing {
+    channel: u16,
+    key: u16,
+    /// Position at which the note ends, unless it is tied.
+    end: f64,
+    tied: bool,
+}
+
+/// Plays the pattern either transposed by the most recently held key, or from the root key
+/// while the transport of the host runs.
+pub struct Sequencer {
+    clock: Clock,
+    /// Keys held down in transpose mode, most recent last.
+    keys: Vec<(u16, u16)>,
+    /// Position of the next step, in beats. Empty while the sequencer is stopped.
+    next_step: Option<f64>,
+    /// Number of steps played since the sequencer started.
+    step: usize,
+    playing: Option<Playing>,
+    /// Value of the modulation lane at the current step.
+    modulation: f32,
+}
+
+impl Sequencer {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            clock: Clock::new(sample_rate),
+            keys: Vec::with_capacity(MAX_KEYS),
+            next_step: None,
+            step: 0,
+            playing: None,
+            modulation: 0.0,
+        }
+    }
+
+    /// Whether the sequencer has notes left to play.
+    pub fn is_active(&self) -> bool {
+        self.next_step.is_some() || self.playing.is_some()
+    }
+
+    pub fn note_on(&mut self, event: &NoteOnEvent) {
+        if let (Match::Specific(channel), Match::Specific(key)) = (event.channel(), event.key()) {
+            self.keys.retain(|held| *held != (channel, key));
+            if self.keys.len() < MAX_KEYS {
+                self.keys.push((channel, key));
+            }
+        }
+    }
+
+    /// Releases a key, returning whether it was one the sequencer was transposing by.
+    pub fn note_off(&mut self, event: &NoteOffEvent) -> bool {
+        let count = self.keys.len();
+        self.keys.retain(|(channel, key)| {
+            event.channel().as_specific() != Some(channel) || event.key().as_specific() != Some(key)
+        });
+        self.keys.len() != count
+    }
+
+    /// Follows the position of the host at the start of a block, if its transport is running.
+    pub fn sync(&mut self, params: &SequencerParams, position: Option<f64>) {
+        self.clock.sync(position);
+        // Jumps in the timeline, such as when the host loops, restart the grid
+        let length = params.rate.beats() as f64;
+        if let Some(grid) = self.next_step {
+            if position.is_some() && (grid - self.clock.beat).abs() > length {
+                self.next_step = Some(self.clock.next_line(length));
+            }
+        }
+    }
+
+    fn is_running(&self, params: &SequencerParams) -> bool {
+        match params.mode {
+            SequencerMode::Off => false,
+            SequencerMode::Transpose => !self.keys.is_empty(),
+            SequencerMode::Play => self.clock.synced,
+        }
+    }
+
+    /// Samples left until the sequencer plays or ends a note, or infinity if it is idle.
+    pub fn pending(&mut self, params: &SequencerParams, tempo: f64) -> f64 {
+        if params.mode != SequencerMode::Transpose {
+            self.keys.clear();
+        }
+
+        if !self.is_running(params) {
+            self.next_step = None;
+            self.modulation = 0.0;
+        } else if self.next_step.is_none() {
+            self.next_step = Some(self.clock.start(params.rate.beats() as f64));
+            self.step = 0;
+        }
+
+        self.upcoming(params).map_or(f64::INFINITY, |(time, _)| {
+            self.clock.samples_until(tempo, time)
+        })
+    }
+
+    pub fn advance(&mut self, tempo: f64, frames: usize) {
+        self.clock.advance(tempo, frames);
+    }
+
+    /// Plays or ends a note if one is due at the current position.
+    pub fn trigger(&mut self, params: &SequencerParams, tempo: f64) -> Option<NoteEvent> {
+        if self.pending(params, tempo) > 0.0 {
+            return None;
+        }
+        let (time, is_end) = self.upcoming(params)?;
+
+        if is_end {
+            let playing = self.playing.take()?;
+            return Some(NoteEvent::off(playing.channel, playing.key));
+        }
+
+        let length = params.rate.beats() as f64;
+        let grid = self.next_step?;
+        // Steps follow the bars of the host while synced, and count from the first key
+        // otherwise
+        let count = if self.clock.synced {
+            (grid / length).round() as i64
+        } else {
+            self.step as i64
+        };
+        let step = params.pattern[count.rem_euclid(params.steps() as i64) as usize];
+
+        let (channel, base) = match params.mode {
+            SequencerMode::Transpose => self.keys.last().copied().unwrap_or_default(),
+            _ => (0, params.root.round() as u16),
+        };
+        let key = (base as f32 + step.pitch.round()).clamp(0.0, 127.0) as u16;
+        let end = if step.tie {
+            grid + length
+        } else {
+            time + length * step.gate as f64
+        };
+
+        if let Some(playing) = self.playing.as_mut() {
+            if step.velocity > 0.0 && (playing.channel, playing.key) == (channel, key) {
+                // A tie into the same note just lets it carry on
+                playing.end = end;
+                playing.tied = step.tie;
+                self.next(params, grid, step);
+                return None;
+            }
+            // The tied note has to end before the step can start its own
+            let playing = self.playing.take()?;
+            return Some(NoteEvent::off(playing.channel, playing.key));
+        }
+
+        self.next(params, grid, step);
+        if step.velocity <= 0.0 {
+            return None;
+        }
+        self.playing = Some(Playing {
+            channel,
+            key,
+            end,
+            tied: step.tie,
+        });
+        Some(NoteEvent::on(channel, key, step.velocity as f64))
+    }
+
+    /// Moves on after playing `step` at `grid`.
+    fn next(&mut self, params: &SequencerParams, grid: f64, step: Step) {
+        self.next_step = Some(grid + params.rate.beats() as f64);
+        self.step += 1;
+        self.modulation = step.modulation;
+    }
+
+    /// Position of the next note to start or end, and whether it is an end.
+    fn upcoming(&self, params: &SequencerParams) -> Option<(f64, bool)> {
+        let end = self.playing.as_ref().map(|playing| {
+            if !self.is_running(params) {
+                self.clock.beat
+            } else if playing.tied {
+                f64::INFINITY
+            } else {
+                playing.end
+            }
+        });
+        match (self.next_step, end) {
+            (None, None) => None,
+            (Some(start), Some(end)) if start < end => Some((start, false)),
+            (_, Some(end)) => Some((end, true)),
+            (Some(start), None) => Some((start, false)),
+        }
+    }
+
+    /// How the modulation lane moves the filters at the current step.
+    pub fn modulation(&self, params: &SequencerParams) -> Lane {
+        let value = self.modulation * params.mod_depth;
+        match params.mod_target {
+            ModTarget::Off => Lane::default(),
+            ModTarget::Cutoff => Lane {
+                cutoff: 2f32.powf(value * CUTOFF_RANGE),
+                ..Lane::default()
+            },
+            ModTarget::Vowel => Lane {
+                vowel: value,
+                ..Lane::default()
+            },
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use crate::wav::Wav;
+
+/// Headers in the order of their hierarchy, each inheriting the opcodes of those before it.
+const HEADERS: [&str; 5] = ["control", "global", "master", "group", "region"];
+/// Level of headers that are not supported, such as `<curve>`, whose opcodes are skipped.
+const IGNORED: usize = usize::MAX;
+
+/// A mono sample, decoded from a WAV file.
+pub struct Sample {
+    pub path: String,
+    pub sample_rate: f32,
+    pub data: Vec<f32>,
+    pub loop_points: Option<(usize, usize)>,
+}
+
+impl Sample {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        let wav = Wav::parse(&data).map_err(|error| format!("{}: {error}", path.display()))?;
+        Ok(Self {
+            path: path.display().to_string(),
+            sample_rate: wav.sample_rate as f32,
+            data: wav.mono(),
+            loop_points: wav.loop_points,
+        })
+    }
+
+    /// Reads the sample at a fractional `position` using 4-point Hermite interpolation.
+    pub fn read(&self, position: f64) -> f32 {
+        let index = position.floor() as isize;
+        let t = (position - index as f64) as f32;
+        let at = |offset: isize| {
+            let i = (index + offset).clamp(0, self.data.len() as isize - 1);
+            self.data[i as usize]
+        };
+        let (y0, y1, y2, y3) = (at(-1), at(0), at(1), at(2));
+
+        let c1 = 0.5 * (y2 - y0);
+        let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+        ((c3 * t + c2) * t + c1) * t + y1
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoopMode {
+    NoLoop,
+    /// Plays the whole sample, ignoring the release of the key.
+    OneShot,
+    Continuous,
+    /// Loops while the key is held, then plays through to the end of the sample.
+    Sustain,
+}
+
+/// Linear fade of a crossfade zone, from silent at `from` to full level at `to`. Zones are
+/// disabled when both ends are equal.
+#[derive(Clone, Copy)]
+struct Fade {
+    from: f32,
+    to: f32,
+}
+
+impl Fade {
+    fn gain(&self, value: f32) -> f32 {
+        if self.from == self.to {
+            return 1.0;
+        }
+        let position = ((value - self.from) / (self.to - self.from)).clamp(0.0, 1.0);
+        // Equal power, so that two layers fading into each other keep a constant loudness
+        position.sqrt()
+    }
+}
+
+pub struct Region {
+    pub sample: Arc<Sample>,
+    pub lokey: u8,
+    pub hikey: u8,
+    pub lovel: u8,
+    pub hivel: u8,
+    pub root: u8,
+    /// Fine tuning in cents, including any transposition of the region.
+    pub tune: f32,
+    pub volume: f32,
+    pub offset: usize,
+    pub loop_mode: LoopMode,
+    pub loop_start: usize,
+    /// Exclusive end of the loop.
+    pub loop_end: usize,
+    /// Length of the crossfade into the loop start, in samples.
+    pub loop_crossfade: usize,
+    fades: [Fade; 4],
+}
+
+impl Region {
+    pub fn matches(&self, key: u8, velocity: u8) -> bool {
+        (self.lokey..=self.hikey).contains(&key) && (self.lovel..=self.hivel).contains(&velocity)
+    }
+
+    /// Level of the region for a note, including its key and velocity crossfades.
+    pub fn gain(&self, key: u8, velocity: u8) -> f32 {
+        let [key_in, key_out, velocity_in, velocity_out] = &self.fades;
+        10f32.powf(self.volume / 20.0)
+            * key_in.gain(key as f32)
+            * key_out.gain(key as f32)
+            * velocity_in.gain(velocity as f32)
+            * velocity_out.gain(velocity as f32)
+    }
+
+    pub fn loops(&self, released: bool) -> bool {
+        match self.loop_mode {
+            LoopMode::Continuous => true,
+            LoopMode::Sustain => !released,
+            LoopMode::NoLoop | LoopMode::OneShot => false,
+        }
+    }
+
+    /// Reads the sample, blending the end of the loop with the audio leading up to its start.
+    pub fn read(&self, position: f64, looping: bool) -> f32 {
+        let sample = self.sample.read(position);
+        let fade_start = (self.loop_end - self.loop_crossfade) as f64;
+        if !looping || self.loop_crossfade == 0 || position < fade_start {
+            return sample;
+        }
+
+        let t = ((position - fade_start) / self.loop_crossfade as f64) as f32;
+        let wrapped = self
+            .sample
+            .read(position - (self.loop_end - self.loop_start) as f64);
+        sample * (1.0 - t) + wrapped * t
+    }
+}
+
+/// The regions of an SFZ file, with all samples loaded into memory.
+pub struct Instrument {
+    /// Identifies the instrument to voices that were started with it, so they can stop if it
+    /// is replaced while they play.
+    pub id: u32,
+    pub path: String,
+    pub regions: Vec<Region>,
+}
+
+impl Instrument {
+    /// Loads an SFZ file and every sample it refers to. This reads from disk and must not be
+    /// called from the audio thread.
+    pub fn load(path: &str) -> Result<Self, String> {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+        let text = std::fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut samples: HashMap<PathBuf, Arc<Sample>> = HashMap::new();
+        let mut regions = Vec::new();
+        for opcodes in parse(&text) {
+            let Some(name) = opcodes.get("sample") else {
+                continue;
+            };
+            let sample_path = directory
+                .join(opcodes.get("default_path").map_or("", String::as_str))
+                .join(name.replace('\\', "/"));
+            let sample = match samples.get(&sample_path) {
+                Some(sample) => sample.clone(),
+                None => {
+                    let sample = Arc::new(Sample::load(&sample_path)?);
+                    samples.insert(sample_path, sample.clone());
+                    sample
+                }
+            };
+            regions.push(region(&opcodes, sample));
+        }
+
+        if regions.is_empty() {
+            return Err(format!("{path}: No regions with samples found"));
+        }
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            path: path.to_string(),
+            regions,
+        })
+    }
+}
+
+/// Splits an SFZ file into the opcodes of each region, with the opcodes of the enclosing
+/// `<control>`, `<global>`, `<master>` and `<group>` headers merged in.
+fn parse(text: &str) -> Vec<HashMap<String, String>> {
+    let mut levels: [HashMap<String, String>; HEADERS.len()] = Default::default();
+    let mut level = 0;
+    let mut regions = Vec::new();
+    let mut current: Option<String> = None;
+
+    let mut finish_region = |levels: &[HashMap<String, String>; HEADERS.len()], level: usize| {
+        if level == HEADERS.len() - 1 {
+            regions.push(levels.iter().flatten().fold(
+                HashMap::new(),
+                |mut merged, (opcode, value)| {
+                    merged.insert(opcode.clone(), value.clone());
+                    merged
+                },
+            ));
+        }
+    };
+
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        // Headers can share a line with opcodes, so they are split off into their own words
+        let words = line
+            .split_whitespace()
+            .flat_map(|word| word.split_inclusive('>'))
+            .flat_map(|word| match word.find('<') {
+                Some(start) if start > 0 => vec![&word[..start], &word[start..]],
+                _ => vec![word],
+            });
+
+        for word in words {
+            if let Some(header) = word.strip_prefix('<').and_then(|w| w.strip_suffix('>')) {
+                current = None;
+                finish_region(&levels, level);
+                level = HEADERS
+                    .iter()
+                    .position(|name| *name == header)
+                    .unwrap_or(IGNORED);
+                if level != IGNORED {
+                    levels[level..].iter_mut().for_each(HashMap::clear);
+                }
+            } else if level == IGNORED {
+                continue;
+            } else if let Some((opcode, value)) = word.split_once('=') {
+                levels[level].insert(opcode.to_string(), value.to_string());
+                current = Some(opcode.to_string());
+            } else if let Some(opcode) = &current {
+                // Values can contain spaces, which is common in sample paths
+                if let Some(value) = levels[level].get_mut(opcode) {
+                    value.push(' ');
+                    value.push_str(word);
+                }
+            }
+        }
+        // Only the sample path may continue past the end of a line
+        current = None;
+    }
+    finish_region(&levels, level);
+    regions
+}
+
+fn region(opcodes: &HashMap<String, String>, sample: Arc<Sample>) -> Region {
+    let number = |opcode: &str| {
+        opcodes
+            .get(opcode)
+            .and_then(|value| value.parse::<f32>().ok())
+    };
+    let note = |opcode: &str| opcodes.get(opcode).and_then(|value| parse_key(value));
+    let fade = |from: &str, to: &str, default: u8| Fade {
+        from: note(from).unwrap_or(default) as f32,
+        to: note(to).unwrap_or(default) as f32,
+    };
+
+    let (lokey, hikey, root) = match note("key") {
+        Some(key) => (key, key, note("pitch_keycenter").unwrap_or(key)),
+        None => (
+            note("lokey").unwrap_or(0),
+            note("hikey").unwrap_or(127),
+            note("pitch_keycenter").unwrap_or(60),
+        ),
+    };
+
+    let (loop_start, loop_end) = match (number("loop_start"), number("loop_end")) {
+        (Some(start), Some(end)) => (start as usize, end as usize + 1),
+        _ => sample.loop_points.unwrap_or((0, sample.data.len())),
+    };
+    let loop_end = loop_end.min(sample.data.len()).max(loop_start + 1);
+    let loop_mode = match opcodes.get("loop_mode").map(String::as_str) {
+        Some("no_loop") => LoopMode::NoLoop,
+        Some("one_shot") => LoopMode::OneShot,
+        Some("loop_continuous") => LoopMode::Continuous,
+        Some("loop_sustain") => LoopMode::Sustain,
+        // Samples with loops embedded in the file loop by default
+        _ if sample.loop_points.is_some() => LoopMode::Continuous,
+        _ => LoopMode::NoLoop,
+    };
+
+    // The crossfade reads from before the loop start, so it can be no longer than that
+    let loop_crossfade = (number("loop_crossfade").unwrap_or(0.0) * sample.sample_rate) as usize;
+    let loop_crossfade = loop_crossfade.min(loop_start).min(loop_end - loop_start);
+
+    Region {
+        lokey,
+        hikey,
+        lovel: note("lovel").unwrap_or(1),
+        hivel: note("hivel").unwrap_or(127),
+        root,
+        tune: number("tune").unwrap_or(0.0) + number("transpose").unwrap_or(0.0) * 100.0,
+        volume: number("volume").unwrap_or(0.0),
+        offset: number("offset").unwrap_or(0.0) as usize,
+        loop_mode,
+        loop_start,
+        loop_end,
+        loop_crossfade,
+        fades: [
+            fade("xfin_lokey", "xfin_hikey", 0),
+            fade("xfout_hikey", "xfout_lokey", 127),
+            fade("xfin_lovel", "xfin_hivel", 0),
+            fade("xfout_hivel", "xfout_lovel", 127),
+        ],
+        sample,
+    }
+}
+
+/// Parses a MIDI note given either as a number or as a name such as `c#4`, where `c4` is 60.
+fn parse_key(value: &str) -> Option<u8> {
+    if let Ok(number) = value.parse::<u8>() {
+        return Some(number.min(127));
+    }
+
+    let value = value.to_ascii_lowercase();
+    let mut chars = value.chars();
+    let mut note: i32 = match chars.next()? {
+        'c' => 0,
+        'd' => 2,
+        'e' => 4,
+        'f' => 5,
+        'g' => 7,
+        'a' => 9,
+        'b' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let octave = match rest.chars().next()? {
+        '#' => {
+            note += 1;
+            &rest[1..]
+        }
+        'b' if rest.len() > 1 => {
+            note -= 1;
+            &rest[1..]
+        }
+        _ => rest,
+    };
+    let key = (octave.parse::<i32>().ok()? + 1) * 12 + note;
+    u8::try_from(key.clamp(0, 127)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(length: usize) -> Arc<Sample> {
+        Arc::new(Sample {
+            path: String::new(),
+            sample_rate: 100.0,
+            data: vec![0.0; length],
+            loop_points: None,
+        })
+    }
+
+    fn single_region(text: &str) -> Region {
+        let regions = parse(text);
+        assert_eq!(regions.len(), 1);
+        region(&regions[0], sample(100))
+    }
+
+    #[test]
+    fn regions_inherit_the_opcodes_of_their_headers() {
+        let regions = parse(
+            "<control> default_path=samples/
+             <global> volume=-6
+             <group> lokey=10 hikey=20
+             <region> sample=a.wav
+             <region> sample=b.wav hikey=30
+             <group>
+             <region> sample=c.wav volume=-3",
+        );
+        assert_eq!(regions.len(), 3);
+
+        assert_eq!(regions[0]["default_path"], "samples/");
+        assert_eq!(regions[0]["volume"], "-6");
+        assert_eq!(regions[0]["lokey"], "10");
+        assert_eq!(regions[0]["hikey"], "20");
+        assert_eq!(regions[1]["sample"], "b.wav");
+        assert_eq!(regions[1]["hikey"], "30");
+        // A new group starts from the global opcodes again
+        assert!(!regions[2].contains_key("lokey"));
+        assert_eq!(regions[2]["volume"], "-3");
+    }
+
+    #[test]
+    fn unsupported_headers_and_comments_are_skipped() {
+        let regions = parse(
+            "// a comment
+             <curve> curve_index=1 v000=0
+             <region> sample=a.wav // volume=-6",
+        );
+        assert_eq!(regions.len(), 1);
+        assert!(!regions[0].contains_key("curve_index"));
+        assert!(!regions[0].contains_key("volume"));
+    }
+
+    #[test]
+    fn sample_paths_keep_their_spaces() {
+        let regions = parse("<region> sample=Grand Piano/C 4.wav lokey=60\nhikey=61");
+        assert_eq!(regions[0]["sample"], "Grand Piano/C 4.wav");
+        assert_eq!(regions[0]["lokey"], "60");
+
+        // Values end with the line
+        let regions = parse("<region> sample=a.wav\nloose words");
+        assert_eq!(regions[0]["sample"], "a.wav");
+    }
+
+    #[test]
+    fn keys_are_parsed_as_numbers_or_names() {
+        assert_eq!(parse_key("60"), Some(60));
+        assert_eq!(parse_key("200"), Some(127));
+        assert_eq!(parse_key("c4"), Some(60));
+        assert_eq!(parse_key("C#4"), Some(61));
+        assert_eq!(parse_key("eb3"), Some(51));
+        assert_eq!(parse_key("b3"), Some(59));
+        assert_eq!(parse_key("c-1"), Some(0));
+        assert_eq!(parse_key("g9"), Some(127));
+        assert_eq!(parse_key("h4"), None);
+        assert_eq!(parse_key("c"), None);
+    }
+
+    #[test]
+    fn regions_read_keys_and_loop_modes() {
+        let region = single_region("<region> sample=a.wav key=c#4 loop_mode=one_shot");
+        assert_eq!((region.lokey, region.hikey, region.root), (61, 61, 61));
+        assert_eq!(region.loop_mode, LoopMode::OneShot);
+        assert!(!region.loops(false));
+
+        let region = single_region("<region> sample=a.wav lokey=10 loop_mode=loop_sustain");
+        assert_eq!((region.lokey, region.hikey, region.root), (10, 127, 60));
+        assert!(region.loops(false));
+        assert!(!region.loops(true));
+    }
+
+    #[test]
+    fn loops_are_clamped_to_the_sample() {
+        let region = single_region("<region> sample=a.wav loop_start=10 loop_end=500");
+        assert_eq!((region.loop_start, region.loop_end), (10, 100));
+
+        let region = single_region("<region> sample=a.wav loop_start=50 loop_end=20");
+        assert_eq!((region.loop_start, region.loop_end), (50, 51));
+
+        let region = single_region("<region> sample=a.wav");
+        assert_eq!((region.loop_start, region.loop_end), (0, 100));
+    }
+
+    #[test]
+    fn crossfades_are_clamped_to_the_audio_before_the_loop() {
+        // One second is 100 samples, more than the 10 before the loop start
+        let region =
+            single_region("<region> sample=a.wav loop_start=10 loop_end=89 loop_crossfade=1");
+        assert_eq!(region.loop_crossfade, 10);
+
+        let region =
+            single_region("<region> sample=a.wav loop_start=50 loop_end=54 loop_crossfade=0.1");
+        assert_eq!(region.loop_crossfade, 5);
+    }
+}
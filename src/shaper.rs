@@ -0,0 +1,174 @@
+use crate::{
+    oversampling::Oversampler,
+    params::{choice, ParamGroup, ParamSpec, Unit},
+};
+
+choice! {
+    pub enum Shape {
+        #[default]
+        Off => "Off",
+        SoftClip => "Soft Clip",
+        HardClip => "Hard Clip",
+        Foldback => "Foldback",
+        Tube => "Tube",
+        BitReduction => "Bit Reduction",
+    }
+}
+
+choice! {
+    pub enum Placement {
+        #[default]
+        PreFilter => "Pre Filter",
+        PostFilter => "Post Filter",
+    }
+}
+
+choice! {
+    pub enum Oversampling {
+        #[default]
+        Off => "Off",
+        Times2 => "2x",
+        Times4 => "4x",
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaperParams {
+    pub shape: Shape,
+    /// Gain into the shaper in dB. For bit reduction it lowers the bit depth instead.
+    pub drive: f32,
+    pub mix: f32,
+    pub placement: Placement,
+    pub oversampling: Oversampling,
+}
+
+impl Default for ShaperParams {
+    fn default() -> Self {
+        Self {
+            shape: Shape::Off,
+            drive: 6.0,
+            mix: 1.0,
+            placement: Placement::PreFilter,
+            oversampling: Oversampling::Times2,
+        }
+    }
+}
+
+impl ParamGroup for ShaperParams {
+    const COUNT: u32 = 5;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Shape", "Shaper", Shape::NAMES)),
+            1 => Some(ParamSpec::new("Drive", "Shaper", 0.0, 36.0, Unit::Decibels)),
+            2 => Some(ParamSpec::new("Mix", "Shaper", 0.0, 1.0, Unit::Percent)),
+            3 => Some(ParamSpec::choice("Placement", "Shaper", Placement::NAMES)),
+            4 => Some(ParamSpec::choice(
+                "Oversampling",
+                "Shaper",
+                Oversampling::NAMES,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.shape.value()),
+            1 => Some(self.drive),
+            2 => Some(self.mix),
+            3 => Some(self.placement.value()),
+            4 => Some(self.oversampling.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.shape = Shape::from_value(value),
+            1 => self.drive = value,
+            2 => self.mix = value,
+            3 => self.placement = Placement::from_value(value),
+            4 => self.oversampling = Oversampling::from_value(value),
+            _ => {}
+        }
+    }
+}
+
+impl Shape {
+    fn apply(self, x: f32, gain: f32) -> f32 {
+        match self {
+            Shape::Off => x,
+            Shape::SoftClip => (x * gain).tanh(),
+            Shape::HardClip => (x * gain).clamp(-1.0, 1.0),
+            Shape::Foldback => {
+                // Reflects the signal back every time it crosses 1 or -1
+                let t = (x * gain + 1.0).rem_euclid(4.0);
+                if t < 2.0 {
+                    t - 1.0
+                } else {
+                    3.0 - t
+                }
+            }
+            Shape::Tube => {
+                // Negative half-waves saturate sooner, which adds even harmonics
+                let x = x * gain;
+                if x >= 0.0 {
+                    1.0 - (-x).exp()
+                } else {
+                    ((2.0 * x).exp() - 1.0) * 0.5
+                }
+            }
+            Shape::BitReduction => {
+                // Here `gain` is the number of quantization steps
+                (x * gain).round() / gain
+            }
+        }
+    }
+}
+
+/// Per-voice waveshaper. Each channel has its own state, as the shaper may come after a
+/// filter that splits the voice into a stereo pair.
+#[derive(Clone, Default)]
+pub struct Shaper {
+    oversamplers: [Oversampler; 2],
+    dc_blockers: [(f32, f32); 2],
+}
+
+impl Shaper {
+    pub fn reset(&mut self) {
+        self.oversamplers.iter_mut().for_each(Oversampler::reset);
+        self.dc_blockers = [(0.0, 0.0); 2];
+    }
+
+    pub fn process(&mut self, params: &ShaperParams, channel: usize, x: f32) -> f32 {
+        if params.shape == Shape::Off {
+            return x;
+        }
+
+        let gain = match params.shape {
+            // From 16 bits at no drive down to 2 bits at full drive
+            Shape::BitReduction => 2f32.powf(15.0 - params.drive / 36.0 * 14.0),
+            _ => 10f32.powf(params.drive / 20.0),
+        };
+        let stages = match params.oversampling {
+            Oversampling::Off => 0,
+            Oversampling::Times2 => 1,
+            Oversampling::Times4 => 2,
+        };
+
+        // The dry signal goes through the oversampler as well, so that both line up
+        let y = self.oversamplers[channel].process(stages, x, |x| {
+            x + (params.shape.apply(x, gain) - x) * params.mix
+        });
+
+        if params.shape != Shape::Tube {
+            return y;
+        }
+        // The asymmetric curve adds an offset, which is removed again
+        let dc_blocker = &mut self.dc_blockers[channel];
+        let output = y - dc_blocker.0 + 0.999 * dc_blocker.1;
+        *dc_blocker = (y, output);
+        output
+    }
+}
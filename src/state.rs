@@ -0,0 +1,31 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+/// Writes a tagged block of data that follows the parameters in the plugin state. Loaders skip
+/// tags they don't know, so new blocks can be added without breaking older states.
+pub fn write_chunk(output: &mut impl Write, tag: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    output.write_all(tag)?;
+    output.write_all(&(data.len() as u32).to_le_bytes())?;
+    output.write_all(data)
+}
+
+/// Reads the next tagged block, or `None` once the end of the state has been reached.
+pub fn read_chunk(input: &mut impl Read) -> io::Result<Option<([u8; 4], Vec<u8>)>> {
+    let mut tag = [0; 4];
+    match input.read_exact(&mut tag) {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error),
+    }
+
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    // The length comes from the host, so the data is read as it arrives rather than allocated
+    // up front, which keeps a corrupt length from reserving gigabytes
+    let len = u32::from_le_bytes(len) as u64;
+    let mut data = Vec::new();
+    input.by_ref().take(len).read_to_end(&mut data)?;
+    if data.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Some((tag, data)))
+}
@@ -0,0 +1,239 @@
+use crate::{
+    dx7::{Dx7Operator, Dx7Params},
+    fm::FrequencyMode,
+};
+
+const BANK_HEADER: [u8; 6] = [0xf0, 0x43, 0x00, 0x09, 0x20, 0x00];
+const BANK_SIZE: usize = 32 * PACKED_VOICE_SIZE;
+const PACKED_VOICE_SIZE: usize = 128;
+const PACKED_OPERATOR_SIZE: usize = 17;
+
+pub struct Dx7Patch {
+    pub name: String,
+    pub params: Dx7Params,
+}
+
+/// A 32-voice bank, kept together with the SysEx data it was loaded from so that it can be
+/// stored in the plugin state.
+#[derive(Default)]
+pub struct Dx7Bank {
+    pub sysex: Vec<u8>,
+    pub patches: Vec<Dx7Patch>,
+}
+
+impl Dx7Bank {
+    /// Parses a 32-voice bulk dump, as found in the `.syx` files of most DX7 patch libraries.
+    /// Files containing only the packed voice data without the SysEx framing are accepted too.
+    pub fn parse(sysex: Vec<u8>) -> Result<Self, &'static str> {
+        let data = if sysex.len() == BANK_SIZE {
+            &sysex[..]
+        } else {
+            if sysex.len() < BANK_HEADER.len() + BANK_SIZE {
+                return Err("File is too short to be a DX7 bank");
+            }
+            // The low nibble of the third byte is the MIDI channel the dump was sent on
+            if sysex[0] != BANK_HEADER[0]
+                || sysex[1] != BANK_HEADER[1]
+                || sysex[2] & 0xf0 != BANK_HEADER[2]
+                || sysex[3..6] != BANK_HEADER[3..6]
+            {
+                return Err("File is not a DX7 32-voice bank");
+            }
+            &sysex[BANK_HEADER.len()..BANK_HEADER.len() + BANK_SIZE]
+        };
+
+        let patches = data
+            .chunks_exact(PACKED_VOICE_SIZE)
+            .map(unpack_voice)
+            .collect();
+        Ok(Self { sysex, patches })
+    }
+}
+
+fn unpack_voice(data: &[u8]) -> Dx7Patch {
+    let mut params = Dx7Params::default();
+
+    // Operators are stored in reverse order, starting with operator 6
+    for (operator, data) in params
+        .operator
+        .iter_mut()
+        .rev()
+        .zip(data.chunks_exact(PACKED_OPERATOR_SIZE))
+    {
+        *operator = unpack_operator(data);
+    }
+
+    params.algorithm = (data[110] & 0x1f) + 1;
+    params.feedback = data[111] & 0x07;
+    params.key_sync = data[111] & 0x08 != 0;
+    params.transpose = data[117].min(48);
+
+    let name = data[118..128]
+        .iter()
+        .map(|c| match c {
+            0x20..=0x7e => *c as char,
+            _ => ' ',
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string();
+
+    Dx7Patch { name, params }
+}
+
+fn unpack_operator(data: &[u8]) -> Dx7Operator {
+    let level = |value: u8| value.min(99);
+    Dx7Operator {
+        rates: [data[0], data[1], data[2], data[3]].map(level),
+        levels: [data[4], data[5], data[6], data[7]].map(level),
+        break_point: level(data[8]),
+        left_depth: level(data[9]),
+        right_depth: level(data[10]),
+        left_curve: data[11] & 0x03,
+        right_curve: (data[11] >> 2) & 0x03,
+        rate_scaling: data[12] & 0x07,
+        detune: ((data[12] >> 3) & 0x0f).min(14),
+        velocity_sensitivity: (data[13] >> 2) & 0x07,
+        output_level: level(data[14]),
+        mode: if data[15] & 0x01 != 0 {
+            FrequencyMode::Fixed
+        } else {
+            FrequencyMode::Ratio
+        },
+        coarse: (data[15] >> 1) & 0x1f,
+        fine: level(data[16]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A packed voice named `name`, with known values in the first and last operator.
+    fn packed_voice(name: &[u8; 10]) -> [u8; PACKED_VOICE_SIZE] {
+        let mut voice = [0; PACKED_VOICE_SIZE];
+
+        // Operator 6 comes first
+        let operator = &mut voice[0..PACKED_OPERATOR_SIZE];
+        operator[0..4].copy_from_slice(&[10, 20, 30, 40]);
+        operator[4..8].copy_from_slice(&[99, 80, 60, 0]);
+        operator[8..11].copy_from_slice(&[39, 12, 34]);
+        // Right curve +Exp, left curve -Exp
+        operator[11] = 0b0110;
+        // Detune and rate scaling
+        operator[12] = 10 << 3 | 5;
+        // Velocity sensitivity and amplitude modulation sensitivity
+        operator[13] = 3 << 2 | 1;
+        operator[14] = 87;
+        // Coarse frequency and fixed mode
+        operator[15] = 2 << 1 | 1;
+        operator[16] = 50;
+
+        // Operator 1, with values beyond their ranges
+        let operator = &mut voice[5 * PACKED_OPERATOR_SIZE..6 * PACKED_OPERATOR_SIZE];
+        operator[0..8].copy_from_slice(&[120, 99, 99, 99, 99, 99, 99, 127]);
+        operator[12] = 15 << 3;
+        operator[15] = 1 << 1;
+
+        voice[110] = 21;
+        voice[111] = 0x08 | 5;
+        voice[117] = 24;
+        voice[118..128].copy_from_slice(name);
+        voice
+    }
+
+    /// A full bulk dump on MIDI channel 3, with its checksum and end of SysEx.
+    fn bulk_dump() -> Vec<u8> {
+        let mut data = Vec::with_capacity(BANK_SIZE);
+        data.extend_from_slice(&packed_voice(b"E.PIANO 1 "));
+        for _ in 1..31 {
+            data.extend_from_slice(&packed_voice(b"          "));
+        }
+        data.extend_from_slice(&packed_voice(b"LAST\x7fVOICE"));
+
+        let checksum =
+            0u8.wrapping_sub(data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))) & 0x7f;
+        let mut sysex = vec![0xf0, 0x43, 0x02, 0x09, 0x20, 0x00];
+        sysex.extend_from_slice(&data);
+        sysex.extend_from_slice(&[checksum, 0xf7]);
+        sysex
+    }
+
+    #[test]
+    fn bulk_dumps_unpack_into_32_patches() {
+        let sysex = bulk_dump();
+        assert_eq!(sysex.len(), 4104);
+        let bank = Dx7Bank::parse(sysex.clone()).unwrap();
+        assert_eq!(bank.sysex, sysex);
+        assert_eq!(bank.patches.len(), 32);
+        assert_eq!(bank.patches[0].name, "E.PIANO 1");
+        assert_eq!(bank.patches[1].name, "");
+        // Characters the DX7 can't display become spaces
+        assert_eq!(bank.patches[31].name, "LAST VOICE");
+    }
+
+    #[test]
+    fn voices_unpack_their_bit_fields() {
+        let params = &Dx7Bank::parse(bulk_dump()).unwrap().patches[0].params;
+        assert_eq!(params.algorithm, 22);
+        assert_eq!(params.feedback, 5);
+        assert!(params.key_sync);
+        assert_eq!(params.transpose, 24);
+
+        let operator = &params.operator[5];
+        assert_eq!(operator.rates, [10, 20, 30, 40]);
+        assert_eq!(operator.levels, [99, 80, 60, 0]);
+        assert_eq!(
+            (
+                operator.break_point,
+                operator.left_depth,
+                operator.right_depth
+            ),
+            (39, 12, 34)
+        );
+        assert_eq!((operator.left_curve, operator.right_curve), (2, 1));
+        assert_eq!((operator.rate_scaling, operator.detune), (5, 10));
+        assert_eq!(operator.velocity_sensitivity, 3);
+        assert_eq!(operator.output_level, 87);
+        assert!(operator.mode == FrequencyMode::Fixed);
+        assert_eq!((operator.coarse, operator.fine), (2, 50));
+
+        // Out of range values are clamped to what the DX7 allows
+        let operator = &params.operator[0];
+        assert_eq!(operator.rates, [99, 99, 99, 99]);
+        assert_eq!(operator.levels, [99, 99, 99, 99]);
+        assert_eq!(operator.detune, 14);
+        assert!(operator.mode == FrequencyMode::Ratio);
+        assert_eq!(operator.coarse, 1);
+    }
+
+    #[test]
+    fn unframed_voice_data_is_accepted() {
+        let data = bulk_dump()[6..6 + BANK_SIZE].to_vec();
+        let bank = Dx7Bank::parse(data).unwrap();
+        assert_eq!(bank.patches[0].name, "E.PIANO 1");
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let sysex = bulk_dump();
+        assert_eq!(
+            Dx7Bank::parse(sysex[..100].to_vec()).err(),
+            Some("File is too short to be a DX7 bank")
+        );
+        assert_eq!(
+            Dx7Bank::parse(sysex[..BANK_SIZE + 5].to_vec()).err(),
+            Some("File is too short to be a DX7 bank")
+        );
+
+        // Another manufacturer, a single voice format and a status other than a bulk dump
+        for (index, value) in [(1, 0x41), (3, 0x00), (2, 0x12)] {
+            let mut sysex = sysex.clone();
+            sysex[index] = value;
+            assert_eq!(
+                Dx7Bank::parse(sysex).err(),
+                Some("File is not a DX7 32-voice bank")
+            );
+        }
+    }
+}
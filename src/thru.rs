@@ -0,0 +1,231 @@
+use clack_plugin::{
+    events::{
+        event_types::{NoteOffEvent, NoteOnEvent},
+        Match, Pckn,
+    },
+    prelude::OutputEvents,
+};
+
+use crate::{
+    notes,
+    params::{choice, ParamGroup, ParamSpec, Unit},
+};
+
+/// Most notes followed at once on the way out.
+const MAX_NOTES: usize = 256;
+
+choice! {
+    pub enum Source {
+        #[default]
+        Off => "Off",
+        Incoming => "Incoming Notes",
+        Played => "Played Notes",
+    }
+}
+
+choice! {
+    pub enum Channel {
+        #[default]
+        Same => "Same",
+        One => "1",
+        Two => "2",
+        Three => "3",
+        Four => "4",
+        Five => "5",
+        Six => "6",
+        Seven => "7",
+        Eight => "8",
+        Nine => "9",
+        Ten => "10",
+        Eleven => "11",
+        Twelve => "12",
+        Thirteen => "13",
+        Fourteen => "14",
+        Fifteen => "15",
+        Sixteen => "16",
+    }
+}
+
+#[derive(Clone)]
+pub struct ThruParams {
+    /// Which notes are sent out: the ones coming in, or the ones the oscillators play after
+    /// the arpeggiator, sequencer and chord memory.
+    pub source: Source,
+    pub transpose: f32,
+    /// Lowest and highest keys that are sent, before transposing.
+    pub key_low: f32,
+    pub key_high: f32,
+    pub velocity: f32,
+    pub channel: Channel,
+}
+
+impl Default for ThruParams {
+    fn default() -> Self {
+        Self {
+            source: Source::Off,
+            transpose: 0.0,
+            key_low: 0.0,
+            key_high: 127.0,
+            velocity: 1.0,
+            channel: Channel::Same,
+        }
+    }
+}
+
+impl ParamGroup for ThruParams {
+    const COUNT: u32 = 6;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice(
+                "Note Output",
+                "Note Output",
+                Source::NAMES,
+            )),
+            1 => Some(ParamSpec::new(
+                "Output Transpose",
+                "Note Output",
+                -48.0,
+                48.0,
+                Unit::Semitones,
+            )),
+            2 => Some(ParamSpec::new(
+                "Output Key Low",
+                "Note Output",
+                0.0,
+                127.0,
+                Unit::Integer,
+            )),
+            3 => Some(ParamSpec::new(
+                "Output Key High",
+                "Note Output",
+                0.0,
+                127.0,
+                Unit::Integer,
+            )),
+            4 => Some(ParamSpec::new(
+                "Output Velocity",
+                "Note Output",
+                0.0,
+                2.0,
+                Unit::Percent,
+            )),
+            5 => Some(ParamSpec::choice(
+                "Output Channel",
+                "Note Output",
+                Channel::NAMES,
+            )),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.source.value()),
+            1 => Some(self.transpose),
+            2 => Some(self.key_low),
+            3 => Some(self.key_high),
+            4 => Some(self.velocity),
+            5 => Some(self.channel.value()),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.source = Source::from_value(value),
+            1 => self.transpose = value,
+            2 => self.key_low = value,
+            3 => self.key_high = value,
+            4 => self.velocity = value,
+            5 => self.channel = Channel::from_value(value),
+            _ => {}
+        }
+    }
+}
+
+/// A note that was sent out and still has to be ended.
+struct Sent {
+    source: Source,
+    /// The note as it came in.
+    pckn: Pckn,
+    /// The note as it went out.
+    output: Pckn,
+}
+
+/// Sends notes to the note output port of the plugin, transformed on the way. Every note that
+/// goes out is remembered with the note it came from, so that its end is sent with the same
+/// key and channel even if the settings change while it plays.
+pub struct Thru {
+    sent: Vec<Sent>,
+}
+
+impl Default for Thru {
+    fn default() -> Self {
+        Self {
+            sent: Vec::with_capacity(MAX_NOTES),
+        }
+    }
+}
+
+impl Thru {
+    /// Sends a note from `source` out, if that is where notes are taken from.
+    pub fn note_on(
+        &mut self,
+        params: &ThruParams,
+        source: Source,
+        event: &NoteOnEvent,
+        output: &mut OutputEvents,
+    ) {
+        if params.source != source || self.sent.len() >= MAX_NOTES {
+            return;
+        }
+        let (Match::Specific(channel), Match::Specific(key)) = (event.channel(), event.key())
+        else {
+            return;
+        };
+        if (key as f32) < params.key_low.round() || key as f32 > params.key_high.round() {
+            return;
+        }
+
+        let key = key as i32 + params.transpose.round() as i32;
+        if !(0..=127).contains(&key) {
+            return;
+        }
+        let channel = match params.channel {
+            Channel::Same => channel,
+            channel => channel.value() as u16 - 1,
+        };
+        let pckn = Pckn {
+            channel: Match::Specific(channel),
+            key: Match::Specific(key as u16),
+            ..event.pckn()
+        };
+        let velocity = (event.velocity() * params.velocity as f64).clamp(0.0, 1.0);
+        // A full queue drops the note, which the host is then left without rather than stuck on
+        if output
+            .try_push(NoteOnEvent::new(event.header().time(), pckn, velocity))
+            .is_ok()
+        {
+            self.sent.push(Sent {
+                source,
+                pckn: event.pckn(),
+                output: pckn,
+            });
+        }
+    }
+
+    /// Ends every note sent from `source` that `event` ends.
+    pub fn note_off(&mut self, source: Source, event: &NoteOffEvent, output: &mut OutputEvents) {
+        let ends =
+            |sent: &Sent| sent.source == source && notes::applies_to(&event.pckn(), &sent.pckn);
+        for sent in self.sent.iter().filter(|sent| ends(sent)) {
+            let _ = output.try_push(NoteOffEvent::new(
+                event.header().time(),
+                sent.output,
+                event.velocity(),
+            ));
+        }
+        self.sent.retain(|sent| !ends(sent));
+    }
+}
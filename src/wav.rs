@@ -0,0 +1,331 @@
+/// The decoded contents of a RIFF WAVE file.
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// Interleaved samples, scaled to the range -1 to 1.
+    pub samples: Vec<f32>,
+    /// Samples per wavetable frame, as declared by the `clm ` chunk that Serum and compatible
+    /// editors write.
+    pub frame_size: Option<usize>,
+    /// Start and end of the first loop in the `smpl` chunk, with the end being exclusive.
+    pub loop_points: Option<(usize, usize)>,
+}
+
+impl Wav {
+    /// Parses 8, 16, 24 and 32-bit integer PCM or 32-bit float data, including files using the
+    /// extensible format header.
+    pub fn parse(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err("File is not a WAV file");
+        }
+
+        let mut format = None;
+        let mut samples = None;
+        let mut frame_size = None;
+        let mut loop_points = None;
+        let mut rest = &data[12..];
+        while rest.len() >= 8 {
+            let id = &rest[0..4];
+            let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let chunk = &rest[8..(8 + len).min(rest.len())];
+            match id {
+                b"fmt " => format = Some(Format::parse(chunk)?),
+                b"data" => samples = Some(chunk),
+                b"clm " => frame_size = parse_clm(chunk),
+                b"smpl" => loop_points = parse_smpl(chunk),
+                _ => {}
+            }
+            // Chunks are padded to an even length
+            rest = &rest[(8 + len + len % 2).min(rest.len())..];
+        }
+
+        let format = format.ok_or("WAV file has no format chunk")?;
+        let samples = samples.ok_or("WAV file has no data chunk")?;
+        Ok(Self {
+            sample_rate: format.sample_rate,
+            channels: format.channels,
+            samples: format.decode(samples)?,
+            frame_size,
+            loop_points,
+        })
+    }
+
+    /// Averages all channels into one.
+    pub fn mono(&self) -> Vec<f32> {
+        self.samples
+            .chunks_exact(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+            .collect()
+    }
+}
+
+struct Format {
+    float: bool,
+    channels: usize,
+    sample_rate: u32,
+    bits: u16,
+}
+
+impl Format {
+    fn parse(chunk: &[u8]) -> Result<Self, &'static str> {
+        if chunk.len() < 16 {
+            return Err("WAV format chunk is too short");
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([chunk[offset], chunk[offset + 1]]);
+
+        // The extensible format keeps the actual format tag at the start of its sub-format GUID
+        let tag = match u16_at(0) {
+            0xfffe if chunk.len() >= 26 => u16_at(24),
+            tag => tag,
+        };
+        let format = Self {
+            float: tag == 3,
+            channels: u16_at(2) as usize,
+            sample_rate: u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]),
+            bits: u16_at(14),
+        };
+
+        match (tag, format.bits) {
+            _ if format.channels == 0 => Err("WAV file has no channels"),
+            (1, 8 | 16 | 24 | 32) | (3, 32) => Ok(format),
+            _ => Err("Unsupported WAV sample format"),
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<f32>, &'static str> {
+        let width = self.bits as usize / 8;
+        let samples: Vec<f32> = data
+            .chunks_exact(width)
+            .map(|bytes| match (self.float, width) {
+                (true, _) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+                // 8-bit samples are the only unsigned ones
+                (false, 1) => (bytes[0] as f32 - 128.0) / 128.0,
+                (false, 2) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+                (false, 3) => {
+                    i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648.0
+                }
+                (false, _) => {
+                    i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                        / 2147483648.0
+                }
+            })
+            .collect();
+
+        if samples.len() < self.channels {
+            return Err("WAV file contains no samples");
+        }
+        Ok(samples)
+    }
+}
+
+/// Reads the frame size from a chunk such as `<!>2048 01000000 wavetable (www.xferrecords.com)`.
+fn parse_clm(chunk: &[u8]) -> Option<usize> {
+    let text = std::str::from_utf8(chunk.strip_prefix(b"<!>")?).ok()?;
+    let digits = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    text[..digits].parse().ok().filter(|size| *size > 0)
+}
+
+/// Reads the first loop of a sampler chunk, which holds 36 bytes of header followed by 24 bytes
+/// per loop with the start and inclusive end at offsets 8 and 12.
+fn parse_smpl(chunk: &[u8]) -> Option<(usize, usize)> {
+    let u32_at = |offset: usize| {
+        let bytes = chunk.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+    if u32_at(28)? == 0 {
+        return None;
+    }
+    let (start, end) = (u32_at(36 + 8)?, u32_at(36 + 12)?);
+    (end > start).then_some((start, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a WAV file from a format tag, channel count, bit depth and the given chunks, with
+    /// the format chunk first.
+    fn wav(tag: u16, channels: u16, bits: u16, chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut format = Vec::new();
+        format.extend_from_slice(&tag.to_le_bytes());
+        format.extend_from_slice(&channels.to_le_bytes());
+        format.extend_from_slice(&44100u32.to_le_bytes());
+        format.extend_from_slice(&(44100 * (channels * bits / 8) as u32).to_le_bytes());
+        format.extend_from_slice(&(channels * bits / 8).to_le_bytes());
+        format.extend_from_slice(&bits.to_le_bytes());
+
+        let mut body = b"WAVE".to_vec();
+        for (id, chunk) in [(b"fmt ", format.as_slice())].iter().chain(chunks) {
+            body.extend_from_slice(*id);
+            body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            body.extend_from_slice(chunk);
+            if chunk.len() % 2 == 1 {
+                body.push(0);
+            }
+        }
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(&body);
+        data
+    }
+
+    fn decode(bits: u16, samples: &[u8]) -> Vec<f32> {
+        Wav::parse(&wav(1, 1, bits, &[(b"data", samples)]))
+            .unwrap()
+            .samples
+    }
+
+    #[test]
+    fn integer_samples_are_scaled() {
+        assert_eq!(decode(8, &[0, 128, 192]), [-1.0, 0.0, 0.5]);
+        let samples: Vec<u8> = [i16::MIN, 0, 16384]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        assert_eq!(decode(16, &samples), [-1.0, 0.0, 0.5]);
+        assert_eq!(
+            decode(24, &[0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40]),
+            [-1.0, 0.0, 0.5]
+        );
+        let samples: Vec<u8> = [i32::MIN, 0, 1 << 30]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        assert_eq!(decode(32, &samples), [-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn float_samples_are_read_as_they_are() {
+        let samples: Vec<u8> = [-0.25f32, 1.5]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let wav = Wav::parse(&wav(3, 1, 32, &[(b"data", &samples)])).unwrap();
+        assert_eq!(wav.samples, [-0.25, 1.5]);
+    }
+
+    #[test]
+    fn extensible_headers_use_their_sub_format() {
+        let mut data = wav(0xfffe, 1, 32, &[(b"data", &0.5f32.to_le_bytes())]);
+        // Grow the format chunk to hold the extension, with the float tag in its GUID
+        let mut extension = vec![22, 0, 32, 0, 0, 0, 0, 0];
+        extension.extend_from_slice(&3u16.to_le_bytes());
+        extension.extend_from_slice(&[0; 14]);
+        data[16] = 16 + extension.len() as u8;
+        data.splice(36..36, extension);
+        assert_eq!(Wav::parse(&data).unwrap().samples, [0.5]);
+    }
+
+    #[test]
+    fn channels_are_averaged_into_mono() {
+        let samples: Vec<u8> = [16384i16, 0, -32768, 0]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        let wav = Wav::parse(&wav(1, 2, 16, &[(b"data", &samples)])).unwrap();
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.mono(), [0.25, -0.5]);
+    }
+
+    #[test]
+    fn unsupported_and_broken_files_are_rejected() {
+        let data = [(b"data", [0u8; 8].as_slice())];
+        assert_eq!(
+            Wav::parse(b"RIFF\0\0\0\0AVI ").err(),
+            Some("File is not a WAV file")
+        );
+        assert_eq!(
+            Wav::parse(&wav(1, 1, 12, &data)).err(),
+            Some("Unsupported WAV sample format")
+        );
+        assert_eq!(
+            Wav::parse(&wav(3, 1, 64, &data)).err(),
+            Some("Unsupported WAV sample format")
+        );
+        assert_eq!(
+            Wav::parse(&wav(1, 0, 16, &data)).err(),
+            Some("WAV file has no channels")
+        );
+        assert_eq!(
+            Wav::parse(&wav(1, 1, 16, &[])).err(),
+            Some("WAV file has no data chunk")
+        );
+        assert_eq!(
+            Wav::parse(&wav(1, 2, 16, &[(b"data", &[0, 0])])).err(),
+            Some("WAV file contains no samples")
+        );
+
+        let mut short = wav(1, 1, 16, &data);
+        short[16] = 14;
+        short.drain(34..36);
+        assert_eq!(
+            Wav::parse(&short).err(),
+            Some("WAV format chunk is too short")
+        );
+    }
+
+    #[test]
+    fn odd_length_chunks_are_padded() {
+        let wav = wav(1, 1, 8, &[(b"junk", &[1, 2, 3]), (b"data", &[128, 255, 0])]);
+        assert_eq!(Wav::parse(&wav).unwrap().samples.len(), 3);
+    }
+
+    #[test]
+    fn truncated_data_is_read_as_far_as_it_goes() {
+        let mut data = wav(1, 1, 16, &[(b"data", &[0, 0, 0, 64])]);
+        // A data chunk claiming more than is there, ending halfway into a sample
+        data.truncate(data.len() - 1);
+        data[40..44].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(Wav::parse(&data).unwrap().samples, [0.0]);
+
+        // A chunk header cut short is ignored
+        let mut data = wav(1, 1, 16, &[(b"data", &[0, 64])]);
+        data.extend_from_slice(b"smp");
+        assert_eq!(Wav::parse(&data).unwrap().samples, [0.5]);
+    }
+
+    #[test]
+    fn frame_sizes_are_read_from_clm_chunks() {
+        assert_eq!(
+            parse_clm(b"<!>2048 01000000 wavetable (www.xferrecords.com)"),
+            Some(2048)
+        );
+        assert_eq!(parse_clm(b"<!>256"), Some(256));
+        assert_eq!(parse_clm(b"<!>0 01000000"), None);
+        assert_eq!(parse_clm(b"<!> 2048"), None);
+        assert_eq!(parse_clm(b"2048 01000000"), None);
+
+        let wav = wav(
+            1,
+            1,
+            8,
+            &[(b"clm ", b"<!>2048 01000000"), (b"data", &[128; 4])],
+        );
+        assert_eq!(Wav::parse(&wav).unwrap().frame_size, Some(2048));
+    }
+
+    #[test]
+    fn loops_are_read_from_smpl_chunks() {
+        let smpl = |loops: u32, start: u32, end: u32| {
+            let mut chunk = vec![0; 36 + 24];
+            chunk[28..32].copy_from_slice(&loops.to_le_bytes());
+            chunk[44..48].copy_from_slice(&start.to_le_bytes());
+            chunk[48..52].copy_from_slice(&end.to_le_bytes());
+            chunk
+        };
+        assert_eq!(parse_smpl(&smpl(1, 10, 19)), Some((10, 20)));
+        assert_eq!(parse_smpl(&smpl(0, 10, 19)), None);
+        assert_eq!(parse_smpl(&smpl(1, 19, 10)), None);
+        assert_eq!(parse_smpl(&smpl(1, 10, 19)[..50]), None);
+
+        let wav = wav(
+            1,
+            1,
+            8,
+            &[(b"data", &[128; 32]), (b"smpl", &smpl(2, 4, 27))],
+        );
+        assert_eq!(Wav::parse(&wav).unwrap().loop_points, Some((4, 28)));
+    }
+}
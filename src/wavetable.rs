@@ -0,0 +1,476 @@
+use std::{
+    f32::consts::TAU,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    oscillator::Generator,
+    params::{choice, ParamGroup, ParamSpec, Parameters, Unit},
+    wav::Wav,
+};
+
+pub const TABLE_SIZE: usize = 2048;
+/// Number of band-limited copies of each frame, each holding half the harmonics of the previous.
+const LEVELS: usize = 11;
+const BUILTIN_FRAMES: usize = 32;
+/// Frames beyond this are ignored when loading a user table, which is as many as Serum uses.
+const MAX_USER_FRAMES: usize = 256;
+/// Time constant of the smoothing applied to position changes, in seconds.
+const POSITION_SMOOTHING: f32 = 0.005;
+
+choice! {
+    pub enum Table {
+        #[default]
+        SineToSaw => "Sine to Saw",
+        SineToSquare => "Sine to Square",
+        PulseWidth => "Pulse Width",
+        Formant => "Formant",
+        User => "User",
+    }
+}
+
+/// A sequence of single-cycle frames, stored as mipmaps so that no harmonic above Nyquist is
+/// played at any pitch.
+pub struct Wavetable {
+    frames: usize,
+    /// Samples of every level of every frame, indexed by `(frame * LEVELS + level) * TABLE_SIZE`
+    data: Vec<f32>,
+}
+
+impl Wavetable {
+    /// Builds a table from single-cycle frames of any length, which are resampled to
+    /// [`TABLE_SIZE`] before being band-limited.
+    pub fn from_frames(frames: &[Vec<f32>]) -> Self {
+        let spectra = frames
+            .iter()
+            .map(|frame| {
+                let mut re = (0..TABLE_SIZE)
+                    .map(|i| {
+                        let position = i as f32 * frame.len() as f32 / TABLE_SIZE as f32;
+                        let (index, fraction) = (position as usize, position.fract());
+                        let next = frame[(index + 1) % frame.len()];
+                        frame[index] + (next - frame[index]) * fraction
+                    })
+                    .collect::<Vec<_>>();
+                let mut im = vec![0.0; TABLE_SIZE];
+                fft(&mut re, &mut im, false);
+                (re, im)
+            })
+            .collect();
+        Self::from_spectra(spectra)
+    }
+
+    /// Builds a table from the sine amplitudes of the harmonics of every frame.
+    fn from_harmonics(frames: impl Iterator<Item = Vec<f32>>) -> Self {
+        let spectra = frames
+            .map(|harmonics| {
+                let re = vec![0.0; TABLE_SIZE];
+                let mut im = vec![0.0; TABLE_SIZE];
+                for (k, amplitude) in harmonics.iter().enumerate().take(TABLE_SIZE / 2 - 1) {
+                    im[k + 1] = -amplitude;
+                    im[TABLE_SIZE - k - 1] = *amplitude;
+                }
+                (re, im)
+            })
+            .collect();
+        Self::from_spectra(spectra)
+    }
+
+    fn from_spectra(spectra: Vec<(Vec<f32>, Vec<f32>)>) -> Self {
+        let mut data = Vec::with_capacity(spectra.len() * LEVELS * TABLE_SIZE);
+        for (re, im) in &spectra {
+            let start = data.len();
+            for level in 0..LEVELS {
+                let harmonics = (TABLE_SIZE / 2) >> level;
+                let (mut level_re, mut level_im) = (re.clone(), im.clone());
+                for k in (0..TABLE_SIZE)
+                    .filter(|k| *k == 0 || (*k > harmonics && *k < TABLE_SIZE - harmonics))
+                {
+                    level_re[k] = 0.0;
+                    level_im[k] = 0.0;
+                }
+                fft(&mut level_re, &mut level_im, true);
+                data.extend_from_slice(&level_re);
+            }
+
+            // Normalize all levels of a frame by the peak of its full-bandwidth version
+            let peak = data[start..start + TABLE_SIZE]
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            if peak > 0.0 {
+                data[start..].iter_mut().for_each(|sample| *sample /= peak);
+            }
+        }
+
+        Self {
+            frames: spectra.len(),
+            data,
+        }
+    }
+
+    /// Reads the table at a `position` between the first and last frame and a `phase` within
+    /// the cycle, using the mipmap level that keeps a note of `frequency` free of aliasing.
+    pub fn sample(&self, position: f32, phase: f32, frequency: f32, sample_rate: f32) -> f32 {
+        let level = ((frequency * TABLE_SIZE as f32 / sample_rate)
+            .log2()
+            .ceil()
+            .max(0.0) as usize)
+            .min(LEVELS - 1);
+        self.interpolate(position, level, phase)
+    }
+
+    /// Reads the full-bandwidth version of the table, for display.
+    pub fn sample_unfiltered(&self, position: f32, phase: f32) -> f32 {
+        self.interpolate(position, 0, phase)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    fn interpolate(&self, position: f32, level: usize, phase: f32) -> f32 {
+        let position = position.clamp(0.0, 1.0) * (self.frames - 1) as f32;
+        let frame = position as usize;
+        let next_frame = (frame + 1).min(self.frames - 1);
+
+        let a = self.read(frame, level, phase);
+        let b = self.read(next_frame, level, phase);
+        a + (b - a) * position.fract()
+    }
+
+    fn read(&self, frame: usize, level: usize, phase: f32) -> f32 {
+        let table = &self.data[(frame * LEVELS + level) * TABLE_SIZE..][..TABLE_SIZE];
+        let position = phase * TABLE_SIZE as f32;
+        let index = position as usize % TABLE_SIZE;
+        let next = table[(index + 1) % TABLE_SIZE];
+        table[index] + (next - table[index]) * position.fract()
+    }
+}
+
+/// In-place radix-2 FFT. The inverse transform is scaled by `1 / len`.
+fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let len = re.len();
+    let bits = len.trailing_zeros();
+    for i in 0..len {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut size = 2;
+    while size <= len {
+        let angle = sign * TAU / size as f32;
+        for start in (0..len).step_by(size) {
+            for k in 0..size / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + size / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+
+    if inverse {
+        re.iter_mut().for_each(|x| *x /= len as f32);
+        im.iter_mut().for_each(|x| *x /= len as f32);
+    }
+}
+
+/// A table loaded from a WAV file, kept together with the file so that it can be stored in the
+/// plugin state.
+pub struct UserWavetable {
+    pub name: String,
+    pub frame_size: usize,
+    pub wav: Vec<u8>,
+    pub table: Wavetable,
+}
+
+impl UserWavetable {
+    /// Splits a WAV file into frames of `frame_size` samples, unless the file declares its own
+    /// frame size in a `clm ` chunk.
+    pub fn load(name: String, wav: Vec<u8>, frame_size: usize) -> Result<Self, &'static str> {
+        let decoded = Wav::parse(&wav)?;
+        let frame_size = decoded.frame_size.unwrap_or(frame_size);
+        if frame_size < 2 {
+            return Err("Frame size must be at least 2 samples");
+        }
+
+        let frames = decoded
+            .mono()
+            .chunks_exact(frame_size)
+            .take(MAX_USER_FRAMES)
+            .map(<[f32]>::to_vec)
+            .collect::<Vec<_>>();
+        if frames.is_empty() {
+            return Err("WAV file is shorter than a single frame");
+        }
+
+        Ok(Self {
+            name,
+            frame_size,
+            wav,
+            table: Wavetable::from_frames(&frames),
+        })
+    }
+
+    /// Serializes the table for the plugin state as the frame size, the name and the WAV file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(8 + self.name.len() + self.wav.len());
+        data.extend_from_slice(&(self.frame_size as u32).to_le_bytes());
+        data.extend_from_slice(&(self.name.len() as u32).to_le_bytes());
+        data.extend_from_slice(self.name.as_bytes());
+        data.extend_from_slice(&self.wav);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, &'static str> {
+        let u32_at = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+                .ok_or("Stored wavetable is truncated")
+        };
+        let frame_size = u32_at(0)?;
+        let name_end = 8 + u32_at(4)?;
+        let name = data
+            .get(8..name_end)
+            .ok_or("Stored wavetable is truncated")?;
+        Self::load(
+            String::from_utf8_lossy(name).into_owned(),
+            data[name_end..].to_vec(),
+            frame_size,
+        )
+    }
+}
+
+/// The tables that ship with the plugin, generated the first time they are needed.
+pub fn builtin() -> &'static [Wavetable] {
+    static TABLES: OnceLock<Vec<Wavetable>> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let morph = |frame: usize| frame as f32 / (BUILTIN_FRAMES - 1) as f32;
+        vec![
+            // Harmonics of a saw wave fade in from the bottom up
+            Wavetable::from_harmonics((0..BUILTIN_FRAMES).map(|frame| {
+                let brightness = 1.0 + 128.0 * morph(frame).powi(2);
+                (1..=512)
+                    .map(|k| (-(k as f32 - 1.0) / brightness).exp() / k as f32)
+                    .collect()
+            })),
+            // The same for the odd harmonics of a square wave
+            Wavetable::from_harmonics((0..BUILTIN_FRAMES).map(|frame| {
+                let brightness = 1.0 + 128.0 * morph(frame).powi(2);
+                (1..=512)
+                    .map(|k| match k % 2 {
+                        1 => (-(k as f32 - 1.0) / brightness).exp() / k as f32,
+                        _ => 0.0,
+                    })
+                    .collect()
+            })),
+            // A pulse narrowing from a square down to a 2% duty cycle
+            Wavetable::from_frames(
+                &(0..BUILTIN_FRAMES)
+                    .map(|frame| {
+                        let width = 0.5 - 0.48 * morph(frame);
+                        (0..TABLE_SIZE)
+                            .map(|i| {
+                                if (i as f32 / TABLE_SIZE as f32) < width {
+                                    1.0 - width
+                                } else {
+                                    -width
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            // A resonant peak sweeping up through the harmonics
+            Wavetable::from_harmonics((0..BUILTIN_FRAMES).map(|frame| {
+                let center = 1.0 + 47.0 * morph(frame);
+                (1..=64)
+                    .map(|k| {
+                        let distance = (k as f32 - center) / 3.0;
+                        (-distance * distance).exp() + 0.1 / k as f32
+                    })
+                    .collect()
+            })),
+        ]
+    })
+}
+
+#[derive(Clone, Default)]
+pub struct WavetableParams {
+    pub table: Table,
+    pub position: f32,
+    /// Offset applied to `position` by host modulation, which is not part of the saved state.
+    pub position_modulation: f32,
+    pub user: Option<Arc<UserWavetable>>,
+}
+
+impl WavetableParams {
+    pub fn position(&self) -> f32 {
+        (self.position + self.position_modulation).clamp(0.0, 1.0)
+    }
+
+    /// The selected table, or `None` if it is the user table and nothing has been loaded.
+    pub fn table<'a>(&'a self, builtin: &'a [Wavetable]) -> Option<&'a Wavetable> {
+        match self.table {
+            Table::User => self.user.as_ref().map(|user| &user.table),
+            table => builtin.get(table as usize),
+        }
+    }
+}
+
+impl ParamGroup for WavetableParams {
+    const COUNT: u32 = 2;
+
+    fn spec(index: u32) -> Option<ParamSpec> {
+        match index {
+            0 => Some(ParamSpec::choice("Table", "Wavetable", Table::NAMES)),
+            1 => {
+                Some(ParamSpec::new("Position", "Wavetable", 0.0, 1.0, Unit::Percent).modulatable())
+            }
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: u32) -> Option<f32> {
+        match index {
+            0 => Some(self.table.value()),
+            1 => Some(self.position),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, index: u32, value: f32) {
+        match index {
+            0 => self.table = Table::from_value(value),
+            1 => self.position = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    fn modulate(&mut self, index: u32, amount: f32) {
+        if index == 1 {
+            self.position_modulation = amount;
+        }
+    }
+}
+
+pub struct WavetableVoice {
+    tables: &'static [Wavetable],
+    phase: f32,
+    position: f32,
+}
+
+impl Default for WavetableVoice {
+    fn default() -> Self {
+        Self {
+            tables: builtin(),
+            phase: 0.0,
+            position: 0.0,
+        }
+    }
+}
+
+impl Generator for WavetableVoice {
+    fn start(&mut self, params: &Parameters, _key: u16, _velocity: f32) {
+        self.phase = 0.0;
+        self.position = params.wavetable.position();
+    }
+
+    fn next_sample(&mut self, params: &Parameters, frequency: f32, sample_rate: f32) -> f32 {
+        let smoothing = 1.0 - (-1.0 / (POSITION_SMOOTHING * sample_rate)).exp();
+        self.position += (params.wavetable.position() - self.position) * smoothing;
+
+        let sample = params.wavetable.table(self.tables).map_or(0.0, |table| {
+            table.sample(self.position, self.phase, frequency, sample_rate)
+        });
+        self.phase = (self.phase + frequency / sample_rate) % 1.0;
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mono 16-bit WAV file holding `frames` saw waves of `frame_size` samples, with a `clm `
+    /// chunk if `declared` is given.
+    fn saw_wav(frames: usize, frame_size: usize, declared: Option<usize>) -> Vec<u8> {
+        let samples: Vec<u8> = (0..frames * frame_size)
+            .map(|i| ((i % frame_size) as f32 / frame_size as f32 * 2.0 - 1.0) * 32767.0)
+            .flat_map(|sample| (sample as i16).to_le_bytes())
+            .collect();
+        let mut chunks = vec![(
+            *b"fmt ",
+            [1, 0, 1, 0, 0x44, 0xac, 0, 0, 0x88, 0x58, 1, 0, 2, 0, 16, 0].to_vec(),
+        )];
+        if let Some(size) = declared {
+            chunks.push((*b"clm ", format!("<!>{size} 00000000").into_bytes()));
+        }
+        chunks.push((*b"data", samples));
+
+        let mut data = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, chunk) in chunks {
+            data.extend_from_slice(&id);
+            data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            data.extend_from_slice(&chunk);
+            if chunk.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn wav_files_are_split_into_frames() {
+        let table = UserWavetable::load("Saw".to_string(), saw_wav(4, 64, None), 64).unwrap();
+        assert_eq!((table.frame_size, table.table.frames()), (64, 4));
+
+        // A frame size declared in the file wins over the one given
+        let table = UserWavetable::load("Saw".to_string(), saw_wav(4, 64, Some(128)), 64).unwrap();
+        assert_eq!((table.frame_size, table.table.frames()), (128, 2));
+
+        assert_eq!(
+            UserWavetable::load("Saw".to_string(), saw_wav(1, 64, None), 128).err(),
+            Some("WAV file is shorter than a single frame")
+        );
+        assert_eq!(
+            UserWavetable::load("Saw".to_string(), saw_wav(1, 64, None), 1).err(),
+            Some("Frame size must be at least 2 samples")
+        );
+    }
+
+    #[test]
+    fn stored_tables_load_back_the_same() {
+        let table =
+            UserWavetable::load("Bright Saw".to_string(), saw_wav(3, 32, None), 32).unwrap();
+        let restored = UserWavetable::from_bytes(&table.to_bytes()).unwrap();
+        assert_eq!(restored.name, "Bright Saw");
+        assert_eq!(restored.frame_size, 32);
+        assert_eq!(restored.wav, table.wav);
+        assert_eq!(restored.table.frames(), 3);
+        assert_eq!(restored.table.data, table.table.data);
+    }
+
+    #[test]
+    fn truncated_tables_are_rejected() {
+        let table = UserWavetable::load("Saw".to_string(), saw_wav(2, 32, None), 32).unwrap();
+        let bytes = table.to_bytes();
+        for len in [0, 6, 8, 10] {
+            assert_eq!(
+                UserWavetable::from_bytes(&bytes[..len]).err(),
+                Some("Stored wavetable is truncated")
+            );
+        }
+        // Cut into the WAV file itself, which then no longer holds the header
+        assert!(UserWavetable::from_bytes(&bytes[..20]).is_err());
+    }
+}